@@ -0,0 +1,217 @@
+//! Computation of new versions.
+
+use anyhow::{Context, Result};
+use cargo_metadata::semver::{Prerelease, Version};
+
+use tracing::warn;
+
+use crate::conventional::ChangeKind;
+use crate::error::bad_input;
+
+/// How to bump a version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BumpLevel {
+    Major,
+    Minor,
+    Patch,
+    Prerelease,
+    Release,
+}
+
+/// What to set the version of each package to.
+#[derive(Clone, Debug)]
+pub enum BumpTarget {
+    /// Compute the next version of each package from its current one
+    Level(BumpLevel),
+    /// Set every package to this exact version
+    Version(Version),
+    /// Compute the next `MAJOR.YYYYMMDD.PATCH` version of each package
+    Calendar,
+    /// Derive the bump level of each package from its Conventional Commits
+    Auto,
+}
+
+/// Parse a bump level, `auto`, or else a literal semver version.
+pub fn parse_bump_target(s: &str) -> Result<BumpTarget> {
+    let level = match s {
+        "major" => BumpLevel::Major,
+        "minor" => BumpLevel::Minor,
+        "patch" => BumpLevel::Patch,
+        "prerelease" => BumpLevel::Prerelease,
+        "release" => BumpLevel::Release,
+        "auto" => return Ok(BumpTarget::Auto),
+        _ => {
+            let version = s
+                .parse()
+                .with_context(|| format!("'{}' is neither a bump level nor a valid version", s))?;
+            return Ok(BumpTarget::Version(version));
+        }
+    };
+    Ok(BumpTarget::Level(level))
+}
+
+/// Increment the last numeric identifier of a pre-release, e.g. `alpha.1` -> `alpha.2`.
+/// A pre-release without a trailing number gets `.1` appended.
+fn bump_prerelease(pre: &Prerelease) -> Result<Prerelease> {
+    let pre = pre.as_str();
+    let (head, last) = match pre.rsplit_once('.') {
+        Some((head, last)) => (Some(head), last),
+        None => (None, pre),
+    };
+    let next = match (head, last.parse::<u64>()) {
+        (Some(head), Ok(n)) => format!("{}.{}", head, n + 1),
+        (None, Ok(n)) => (n + 1).to_string(),
+        (_, Err(_)) => format!("{}.1", pre),
+    };
+    Ok(Prerelease::new(&next)?)
+}
+
+/// Compute the next version for `level`. Like `npm version`, bumping a pre-release whose
+/// lower components are already zero just releases it, e.g. `1.0.0-beta.2` -> `1.0.0` for major.
+fn bump_version(current: &Version, level: BumpLevel) -> Result<Version> {
+    let mut next = Version::new(current.major, current.minor, current.patch);
+    let is_pre = !current.pre.is_empty();
+    match level {
+        BumpLevel::Major => {
+            if !(is_pre && current.minor == 0 && current.patch == 0) {
+                next.major += 1;
+                next.minor = 0;
+                next.patch = 0;
+            }
+        }
+        BumpLevel::Minor => {
+            if !(is_pre && current.patch == 0) {
+                next.minor += 1;
+                next.patch = 0;
+            }
+        }
+        BumpLevel::Patch => {
+            if !is_pre {
+                next.patch += 1;
+            }
+        }
+        BumpLevel::Prerelease => {
+            if !is_pre {
+                next.patch += 1;
+                next.pre = Prerelease::new("alpha.1")?;
+            } else {
+                next.pre = bump_prerelease(&current.pre)?;
+            }
+        }
+        BumpLevel::Release => {
+            if !is_pre {
+                return Err(bad_input(format!(
+                    "{} is not a pre-release version",
+                    current
+                )));
+            }
+        }
+    }
+    Ok(next)
+}
+
+/// Today's date in UTC as `YYYYMMDD`.
+pub(crate) fn today() -> u64 {
+    let days = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .expect("system time shall be after the UNIX epoch")
+        .as_secs()
+        / 86400;
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z % 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    year * 10000 + month * 100 + day
+}
+
+/// Compute the next `MAJOR.YYYYMMDD.PATCH` version. Releasing twice on the same day bumps
+/// the patch component.
+fn bump_calendar_version(current: &Version, today: u64) -> Version {
+    if current.minor >= today {
+        Version::new(current.major, current.minor, current.patch + 1)
+    } else {
+        Version::new(current.major, today, 0)
+    }
+}
+
+impl BumpTarget {
+    /// Resolve `Auto` with the most significant change of a package.
+    pub fn resolve(&self, change_kind: ChangeKind, current: &Version) -> BumpTarget {
+        match self {
+            BumpTarget::Auto => BumpTarget::Level(change_kind.bump_level(current)),
+            target => target.clone(),
+        }
+    }
+
+    pub fn next_version(&self, current: &Version) -> Result<Version> {
+        match self {
+            BumpTarget::Level(level) => bump_version(current, *level),
+            BumpTarget::Version(version) => Ok(version.clone()),
+            BumpTarget::Calendar => Ok(bump_calendar_version(current, today())),
+            BumpTarget::Auto => anyhow::bail!("bump level shall be resolved for each package"),
+        }
+    }
+}
+
+/// Refuse to move a version backwards or leave it unchanged, unless `allow_downgrade`.
+pub(crate) fn check_upgrade(
+    what: &str,
+    current: &Version,
+    new: &Version,
+    allow_downgrade: bool,
+) -> Result<()> {
+    if new > current {
+        return Ok(());
+    }
+    if allow_downgrade {
+        warn!(
+            "Setting {} from '{}' to '{}', which is not an upgrade",
+            what, current, new
+        );
+        return Ok(());
+    }
+    Err(bad_input(format!(
+        "new version '{}' of {} is not greater than its current version '{}', use --allow-downgrade to set it anyway",
+        new, what, current
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bumps_versions() {
+        let cases = [
+            ("1.2.3", BumpLevel::Major, "2.0.0"),
+            ("1.2.3", BumpLevel::Minor, "1.3.0"),
+            ("1.2.3", BumpLevel::Patch, "1.2.4"),
+            ("1.2.3", BumpLevel::Prerelease, "1.2.4-alpha.1"),
+            ("1.0.0-beta.2", BumpLevel::Major, "1.0.0"),
+            ("1.1.0-beta.2", BumpLevel::Major, "2.0.0"),
+            ("1.2.0-rc.1", BumpLevel::Minor, "1.2.0"),
+            ("1.2.1-rc.1", BumpLevel::Minor, "1.3.0"),
+            ("1.2.3-rc.1", BumpLevel::Patch, "1.2.3"),
+            ("1.2.3-rc.1", BumpLevel::Release, "1.2.3"),
+            ("1.2.3-alpha.1", BumpLevel::Prerelease, "1.2.3-alpha.2"),
+            ("1.2.3-alpha", BumpLevel::Prerelease, "1.2.3-alpha.1"),
+            ("1.2.3-9", BumpLevel::Prerelease, "1.2.3-10"),
+            ("1.2.3+build.5", BumpLevel::Patch, "1.2.4"),
+        ];
+        for (current, level, expected) in cases {
+            let next = bump_version(&current.parse().unwrap(), level).unwrap();
+            assert_eq!(next.to_string(), expected, "{} {:?}", current, level);
+        }
+    }
+
+    #[test]
+    fn refuses_to_release_a_release() {
+        assert!(bump_version(&Version::new(1, 2, 3), BumpLevel::Release).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! Changelog sections in the [Keep a Changelog](https://keepachangelog.com/) format.
+
+use crate::conventional::ConventionalCommit;
+
+const HEADER: &str =
+    "# Changelog\n\nAll notable changes to this project will be documented in this file.\n";
+
+/// Commit types not worth a changelog entry.
+const SKIPPED_TYPES: [&str; 6] = ["build", "chore", "ci", "docs", "style", "test"];
+
+/// Render the section of a release from the messages of the commits it contains.
+pub fn render_section(version: &str, date: &str, messages: &[&str]) -> String {
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    let mut fixed = Vec::new();
+    for message in messages {
+        let Some(commit) = ConventionalCommit::parse(message) else {
+            let subject = message.lines().next().unwrap_or_default().trim();
+            if !subject.is_empty() {
+                changed.push(subject.to_string());
+            }
+            continue;
+        };
+        let commit_type = commit.commit_type.to_ascii_lowercase();
+        if !commit.breaking && SKIPPED_TYPES.contains(&commit_type.as_str()) {
+            continue;
+        }
+        let entry = if commit.breaking {
+            format!("**Breaking:** {}", commit.description)
+        } else {
+            commit.description.to_string()
+        };
+        match commit_type.as_str() {
+            "feat" => added.push(entry),
+            "fix" => fixed.push(entry),
+            _ => changed.push(entry),
+        }
+    }
+
+    let mut section = format!("## [{}] - {}\n", version, date);
+    if added.is_empty() && changed.is_empty() && fixed.is_empty() {
+        changed.push("Version bump only.".to_string());
+    }
+    for (heading, entries) in [("Added", added), ("Changed", changed), ("Fixed", fixed)] {
+        if entries.is_empty() {
+            continue;
+        }
+        section.push_str(&format!("\n### {}\n\n", heading));
+        for entry in entries {
+            section.push_str(&format!("- {}\n", entry));
+        }
+    }
+    section
+}
+
+/// Insert a release section above the latest release, keeping the title and an
+/// `[Unreleased]` section on top. `None` creates a new changelog.
+pub fn prepend_section(changelog: Option<&str>, section: &str) -> String {
+    let changelog = changelog.unwrap_or(HEADER);
+    let mut offset = 0;
+    for line in changelog.split_inclusive('\n') {
+        if line.starts_with("## ") && !line.to_ascii_lowercase().contains("unreleased") {
+            return format!(
+                "{}{}\n{}",
+                &changelog[..offset],
+                section,
+                &changelog[offset..]
+            );
+        }
+        offset += line.len();
+    }
+    // No release yet
+    let mut changelog = changelog.trim_end().to_string();
+    changelog.push_str("\n\n");
+    changelog.push_str(section);
+    changelog
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECTION: &str = "## [0.2.0] - 2025-01-02\n\n### Fixed\n\n- bar\n";
+
+    #[test]
+    fn creates_changelog() {
+        assert_eq!(
+            prepend_section(None, SECTION),
+            format!("{}\n{}", HEADER, SECTION)
+        );
+    }
+
+    #[test]
+    fn prepends_section_below_unreleased() {
+        let changelog =
+            "# Changelog\n\n## [Unreleased]\n\n- wip\n\n## [0.1.0] - 2025-01-01\n\n- foo\n";
+        assert_eq!(
+            prepend_section(Some(changelog), SECTION),
+            format!(
+                "# Changelog\n\n## [Unreleased]\n\n- wip\n\n{}\n## [0.1.0] - 2025-01-01\n\n- foo\n",
+                SECTION
+            )
+        );
+    }
+
+    #[test]
+    fn appends_first_release() {
+        assert_eq!(
+            prepend_section(Some("# Changelog\n\n\n"), SECTION),
+            format!("# Changelog\n\n{}", SECTION)
+        );
+    }
+}
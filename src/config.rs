@@ -0,0 +1,192 @@
+//! Settings from `[workspace.metadata.jump]` and `[package.metadata.jump]`.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use cargo_metadata::{Metadata, Package};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum VersionScheme {
+    /// A version or bump level has to be given explicitly
+    #[default]
+    Semver,
+    /// Versions look like `MAJOR.YYYYMMDD.PATCH`, and are computed when no version is given
+    Calver,
+}
+
+/// Settings from `[workspace.metadata.jump]`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WorkspaceConfig {
+    /// Prefix of release tags, e.g. `v` for `v1.2.3`
+    pub tag_prefix: String,
+    /// Glob pattern of release tags to compare against when no old tag is given,
+    /// `{tag-prefix}*` by default. `{name}` is replaced by each package name.
+    pub tag_pattern: Option<String>,
+    /// Release packages independently, each compared against its own tag
+    pub independent: bool,
+    /// Format of per-package tags, `{name}-{tag-prefix}{version}` by default
+    pub tag_format: Option<String>,
+    /// Files of each package to ignore, relative to the package directory
+    pub ignore: Vec<String>,
+    /// Extra files to consider for each package, relative to the workspace root
+    pub include: Vec<String>,
+    /// Also consider files listed in dep-info files of a previous build, e.g. those included
+    /// with `include_str!` from outside the package directory
+    pub dep_info: bool,
+    /// Also consider members whose resolved dependencies changed in `Cargo.lock`
+    pub lockfile_changes: bool,
+    /// Dotted paths of manifest fields whose changes don't count, e.g. `package.metadata`
+    pub manifest_ignore: Vec<String>,
+    /// Packages never to bump
+    pub skip: Vec<String>,
+    /// Never bump packages with `publish = false`
+    pub skip_unpublished: bool,
+    /// Also bump workspace members depending on affected packages
+    pub propagate: bool,
+    /// How versions are computed, and whether one has to be given
+    pub version_scheme: VersionScheme,
+    /// Add a section for the new version to the changelog of each bumped package
+    pub changelog: bool,
+    /// Changelog of each package, relative to the package directory
+    pub changelog_file: String,
+    /// Message of the release commit created by `--commit`, see `commit_message`
+    pub commit_message: String,
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> Self {
+        Self {
+            tag_prefix: "v".to_string(),
+            tag_pattern: None,
+            independent: false,
+            tag_format: None,
+            ignore: Vec::new(),
+            include: Vec::new(),
+            dep_info: false,
+            lockfile_changes: false,
+            manifest_ignore: vec!["package.metadata".to_string()],
+            skip: Vec::new(),
+            skip_unpublished: false,
+            propagate: false,
+            version_scheme: VersionScheme::default(),
+            changelog: false,
+            changelog_file: "CHANGELOG.md".to_string(),
+            commit_message: "Bump versions\n\n{packages}".to_string(),
+        }
+    }
+}
+
+/// Settings from `[package.metadata.jump]`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PackageConfig {
+    /// Files of the package to ignore, relative to the package directory
+    pub ignore: Vec<String>,
+    /// Extra files to consider, relative to the workspace root
+    pub include: Vec<String>,
+    /// Never bump this package
+    pub skip: bool,
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub workspace: WorkspaceConfig,
+    packages: BTreeMap<String, PackageConfig>,
+}
+
+/// Effective settings, as printed by `--show-config`.
+#[derive(Serialize)]
+struct EffectiveConfig<'a> {
+    workspace: &'a WorkspaceConfig,
+    package: BTreeMap<&'a str, PackageConfig>,
+}
+
+fn jump_section<T: Default + for<'de> Deserialize<'de>>(metadata: &serde_json::Value) -> Result<T> {
+    match metadata.get("jump") {
+        Some(section) => Ok(serde_json::from_value(section.clone())?),
+        None => Ok(T::default()),
+    }
+}
+
+impl Config {
+    pub fn load(metadata: &Metadata, members: &[&Package]) -> Result<Self> {
+        let workspace = jump_section(&metadata.workspace_metadata)
+            .context("invalid [workspace.metadata.jump]")?;
+        let mut packages = BTreeMap::new();
+        for package in members {
+            let config = jump_section(&package.metadata).with_context(|| {
+                format!("invalid [package.metadata.jump] of '{}'", package.name)
+            })?;
+            packages.insert(package.name.to_string(), config);
+        }
+        Ok(Self {
+            workspace,
+            packages,
+        })
+    }
+
+    pub fn tag_pattern(&self) -> String {
+        match &self.workspace.tag_pattern {
+            Some(tag_pattern) => tag_pattern.clone(),
+            None => format!("{}*", self.workspace.tag_prefix),
+        }
+    }
+
+    /// Name of the tag of a package release. `version` may also be a glob pattern.
+    pub fn package_tag(&self, name: &str, version: &str) -> String {
+        let tag_format = match &self.workspace.tag_format {
+            Some(tag_format) => tag_format.clone(),
+            None => format!("{{name}}-{}{{version}}", self.workspace.tag_prefix),
+        };
+        tag_format
+            .replace("{name}", name)
+            .replace("{version}", version)
+    }
+
+    /// Settings of a package merged with the workspace-wide ones.
+    pub fn package(&self, package: &Package) -> PackageConfig {
+        let config = self
+            .packages
+            .get(package.name.as_str())
+            .cloned()
+            .unwrap_or_default();
+        let unpublished = package.publish.as_ref().is_some_and(|it| it.is_empty());
+        PackageConfig {
+            ignore: self
+                .workspace
+                .ignore
+                .iter()
+                .chain(&config.ignore)
+                .cloned()
+                .collect(),
+            include: self
+                .workspace
+                .include
+                .iter()
+                .chain(&config.include)
+                .cloned()
+                .collect(),
+            skip: config.skip
+                || self
+                    .workspace
+                    .skip
+                    .iter()
+                    .any(|it| it == package.name.as_str())
+                || (self.workspace.skip_unpublished && unpublished),
+        }
+    }
+
+    pub fn to_toml(&self, members: &[&Package]) -> Result<String> {
+        let effective = EffectiveConfig {
+            workspace: &self.workspace,
+            package: members
+                .iter()
+                .map(|p| (p.name.as_str(), self.package(p)))
+                .collect(),
+        };
+        Ok(toml_edit::ser::to_string_pretty(&effective)?)
+    }
+}
@@ -0,0 +1,73 @@
+//! Classification of commit messages following <https://www.conventionalcommits.org/>.
+
+use cargo_metadata::semver::Version;
+
+use crate::bump::BumpLevel;
+
+/// Kind of change made by a commit, ordered by significance.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChangeKind {
+    #[default]
+    Fix,
+    Feature,
+    Breaking,
+}
+
+/// A commit message following Conventional Commits.
+pub struct ConventionalCommit<'a> {
+    pub commit_type: &'a str,
+    pub breaking: bool,
+    pub description: &'a str,
+}
+
+impl<'a> ConventionalCommit<'a> {
+    pub fn parse(message: &'a str) -> Option<Self> {
+        let mut lines = message.lines();
+        let subject = lines.next()?;
+        let (prefix, description) = subject.split_once(':')?;
+        let (prefix, bang) = match prefix.strip_suffix('!') {
+            Some(prefix) => (prefix, true),
+            None => (prefix, false),
+        };
+        let commit_type = match prefix.split_once('(') {
+            Some((commit_type, scope)) if scope.ends_with(')') => commit_type,
+            Some(_) => return None,
+            None => prefix,
+        };
+        if commit_type.is_empty() || !commit_type.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None;
+        }
+        let breaking = bang
+            || lines.any(|line| {
+                line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:")
+            });
+        Some(Self {
+            commit_type,
+            breaking,
+            description: description.trim(),
+        })
+    }
+}
+
+impl ChangeKind {
+    /// Classify a commit message. Messages not following Conventional Commits count as fixes.
+    pub fn of(message: &str) -> Self {
+        match ConventionalCommit::parse(message) {
+            Some(commit) if commit.breaking => Self::Breaking,
+            Some(commit) if commit.commit_type.eq_ignore_ascii_case("feat") => Self::Feature,
+            _ => Self::Fix,
+        }
+    }
+
+    /// Bump level for this kind of change. Before 1.0.0, Cargo treats the minor version as
+    /// the compatibility boundary, so breaking changes bump the minor and features the patch.
+    pub fn bump_level(self, current: &Version) -> BumpLevel {
+        match (self, current.major) {
+            (Self::Breaking, 0) => BumpLevel::Minor,
+            (Self::Breaking, _) => BumpLevel::Major,
+            (Self::Feature, 0) => BumpLevel::Patch,
+            (Self::Feature, _) => BumpLevel::Minor,
+            (Self::Fix, _) => BumpLevel::Patch,
+        }
+    }
+}
@@ -0,0 +1,470 @@
+//! Attribution of changed files to workspace members.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::Result;
+use cargo_metadata::{DependencyKind, Metadata, Package, PackageId};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use tracing::{debug, info, warn};
+
+use crate::config::{Config, PackageConfig};
+use crate::error::bad_input;
+
+fn build_globset(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(GlobBuilder::new(pattern).literal_separator(true).build()?);
+    }
+    Ok(builder.build()?)
+}
+
+/// Glob patterns deciding which changed files count for a package.
+struct ChangeFilter {
+    /// Files of the package to ignore, relative to the package directory
+    ignore: GlobSet,
+    /// Extra files to consider, relative to the workspace root
+    include: GlobSet,
+}
+
+impl ChangeFilter {
+    fn new(config: &PackageConfig) -> Result<Self> {
+        Ok(Self {
+            ignore: build_globset(&config.ignore)?,
+            include: build_globset(&config.include)?,
+        })
+    }
+}
+
+/// Find the workspace member a file belongs to, i.e. the one with the deepest directory
+/// containing it. Files inside a nested package which is not a workspace member (e.g. an
+/// excluded one) belong to no member, as do files of a package which has been moved or
+/// deleted, whose directory is in `removed_package_dirs`.
+fn owning_package<'a, 'b>(
+    file: &'b Path,
+    member_dirs: &BTreeMap<&Path, &'a Package>,
+    removed_package_dirs: &BTreeSet<&Path>,
+) -> Option<(&'b Path, &'a Package)> {
+    for dir in file.ancestors().skip(1) {
+        if let Some(package) = member_dirs.get(dir) {
+            return Some((dir, package));
+        }
+        if dir.join("Cargo.toml").is_file() || removed_package_dirs.contains(dir) {
+            return None;
+        }
+    }
+    None
+}
+
+/// Resolve `.` and `..` components without touching the file system, as paths from
+/// manifests may contain them, e.g. `crates/a/../../src/lib.rs`.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Files a package is built from besides those in its directory: target sources, e.g.
+/// `[lib] path = "../src/lib.rs"` or `build = "../build.rs"`, its readme and license file.
+fn referenced_files(package: &Package) -> Vec<PathBuf> {
+    package
+        .targets
+        .iter()
+        .map(|it| it.src_path.clone())
+        .chain(package.readme())
+        .chain(package.license_file())
+        .map(|it| normalize(it.as_std_path()))
+        .collect()
+}
+
+/// Parse a Makefile-style dep-info file written by rustc or cargo, returning the
+/// dependencies of its outputs, which are relative to `base` unless absolute.
+fn parse_dep_info(content: &str, base: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for line in content.lines() {
+        if line.starts_with('#') {
+            continue;
+        }
+        let Some((_, dependencies)) = line.split_once(": ") else {
+            continue;
+        };
+        // Spaces in paths are escaped with a backslash
+        let mut file = String::new();
+        let mut chars = dependencies.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => file.extend(chars.next()),
+                ' ' if !file.is_empty() => {
+                    files.push(normalize(&base.join(std::mem::take(&mut file))))
+                }
+                ' ' => {}
+                c => file.push(c),
+            }
+        }
+        if !file.is_empty() {
+            files.push(normalize(&base.join(file)));
+        }
+    }
+    files
+}
+
+/// Collect rustc's dep-info files of previous builds under a target directory, skipping
+/// directories which never contain any. Those cargo writes next to final artifacts list the
+/// sources of path dependencies too, so only the per-crate ones in `deps` and `examples`
+/// are collected.
+fn find_dep_info_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            let name = entry.file_name();
+            if !matches!(
+                name.to_str(),
+                Some("build" | "incremental" | ".fingerprint" | "doc")
+            ) {
+                find_dep_info_files(&path, files);
+            }
+        } else if path.extension() == Some(OsStr::new("d"))
+            && matches!(
+                dir.file_name().and_then(OsStr::to_str),
+                Some("deps" | "examples")
+            )
+        {
+            files.push(path);
+        }
+    }
+}
+
+/// Attribute the files listed in a rustc dep-info file to the member whose target is the
+/// crate root, which rustc lists first. Files of other members are left out, so that only
+/// files outside of any member, e.g. those included with `include_str!`, are added.
+fn attribute_dep_info<'a>(
+    files: &[PathBuf],
+    workspace_root: &Path,
+    member_dirs: &BTreeMap<&Path, &'a Package>,
+) -> Option<(&'a Package, Vec<PathBuf>)> {
+    let root = files.first()?;
+    let package = *member_dirs.values().find(|p| {
+        p.targets
+            .iter()
+            .any(|it| normalize(it.src_path.as_std_path()) == *root)
+    })?;
+    let files = files
+        .iter()
+        .filter(|it| it.starts_with(workspace_root))
+        .filter(
+            |it| match owning_package(it, member_dirs, &BTreeSet::new()) {
+                Some((_, owner)) => owner.id == package.id,
+                None => true,
+            },
+        )
+        .cloned()
+        .collect();
+    Some((package, files))
+}
+
+/// Files each member has been built from according to the dep-info files of a previous
+/// build, e.g. those included with `include_str!`.
+fn dep_info_files<'a>(
+    metadata: &Metadata,
+    member_dirs: &BTreeMap<&Path, &'a Package>,
+) -> BTreeMap<&'a PackageId, Vec<PathBuf>> {
+    let workspace_root = metadata.workspace_root.as_std_path();
+    let build_dir = metadata
+        .build_directory
+        .as_ref()
+        .unwrap_or(&metadata.target_directory);
+    let mut dep_info_paths = Vec::new();
+    find_dep_info_files(build_dir.as_std_path(), &mut dep_info_paths);
+    if build_dir != &metadata.target_directory {
+        find_dep_info_files(metadata.target_directory.as_std_path(), &mut dep_info_paths);
+    }
+    if dep_info_paths.is_empty() {
+        warn!(
+            "No dep-info files found in {}, build the workspace first",
+            build_dir
+        );
+    }
+
+    let mut package_files: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for dep_info_path in dep_info_paths {
+        let Ok(content) = std::fs::read_to_string(&dep_info_path) else {
+            continue;
+        };
+        let files = parse_dep_info(&content, workspace_root);
+        if let Some((package, files)) = attribute_dep_info(&files, workspace_root, member_dirs) {
+            debug!(
+                "Using dep-info file {} for package '{}'",
+                dep_info_path.display(),
+                package.name
+            );
+            package_files.entry(&package.id).or_default().extend(files);
+        }
+    }
+    package_files
+}
+
+/// Workspace members, with what is needed to attribute changed files to them.
+pub struct ChangeDetector<'a> {
+    workspace_root: &'a Path,
+    members: Vec<&'a Package>,
+    member_dirs: BTreeMap<&'a Path, &'a Package>,
+    change_filters: BTreeMap<&'a PackageId, ChangeFilter>,
+    /// Members using each file outside their directory
+    outside_files: BTreeMap<PathBuf, Vec<&'a Package>>,
+}
+
+impl<'a> ChangeDetector<'a> {
+    pub fn new(metadata: &'a Metadata, members: &[&'a Package], config: &Config) -> Result<Self> {
+        let workspace_root = metadata.workspace_root.as_std_path();
+        let member_dirs: BTreeMap<_, _> = members
+            .iter()
+            .map(|&p| {
+                let manifest_dir = p
+                    .manifest_path
+                    .as_std_path()
+                    .parent()
+                    .expect("manifest path shall have a parent directory");
+                (manifest_dir, p)
+            })
+            .collect();
+        let mut change_filters = BTreeMap::new();
+        for &package in members {
+            let filter = ChangeFilter::new(&config.package(package)).map_err(|err| {
+                bad_input(format!(
+                    "invalid ignore or include patterns of '{}': {:#}",
+                    package.name, err
+                ))
+            })?;
+            change_filters.insert(&package.id, filter);
+        }
+
+        let mut dep_info = if config.workspace.dep_info {
+            dep_info_files(metadata, &member_dirs)
+        } else {
+            BTreeMap::new()
+        };
+        let mut outside_files: BTreeMap<_, Vec<&Package>> = BTreeMap::new();
+        for &package in members {
+            let manifest_dir = package
+                .manifest_path
+                .parent()
+                .expect("manifest path shall have a parent directory");
+            let mut files = referenced_files(package);
+            files.extend(dep_info.remove(&package.id).into_iter().flatten());
+            for file in files {
+                if file.starts_with(manifest_dir) {
+                    continue;
+                }
+                let packages = outside_files.entry(file).or_default();
+                if !packages.iter().any(|p| p.id == package.id) {
+                    packages.push(package);
+                }
+            }
+        }
+
+        Ok(Self {
+            workspace_root,
+            members: members.to_vec(),
+            member_dirs,
+            change_filters,
+            outside_files,
+        })
+    }
+
+    /// Attribute changed files to workspace members, returning the changed files of each
+    /// member.
+    pub fn changed_packages(
+        &self,
+        changed_files: &[PathBuf],
+    ) -> BTreeMap<&'a PackageId, Vec<PathBuf>> {
+        let removed_package_dirs: BTreeSet<_> = changed_files
+            .iter()
+            .filter(|it| it.file_name() == Some(OsStr::new("Cargo.toml")) && !it.exists())
+            .filter_map(|it| it.parent())
+            .collect();
+        let mut package_changes: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for changed_file in changed_files {
+            if let Some((package_dir, package)) =
+                owning_package(changed_file, &self.member_dirs, &removed_package_dirs)
+            {
+                let relative_path = changed_file
+                    .strip_prefix(package_dir)
+                    .expect("file shall be inside its package directory");
+                if self.change_filters[&package.id]
+                    .ignore
+                    .is_match(relative_path)
+                {
+                    debug!(
+                        "File {} is ignored for package '{}'",
+                        changed_file.display(),
+                        package.name
+                    );
+                } else {
+                    package_changes
+                        .entry(&package.id)
+                        .or_default()
+                        .push(changed_file.clone());
+                }
+            } else {
+                debug!(
+                    "File {} does not belong to any workspace member",
+                    changed_file.display()
+                );
+            }
+            for &package in self.outside_files.get(changed_file).into_iter().flatten() {
+                debug!(
+                    "File {} is used by package '{}'",
+                    changed_file.display(),
+                    package.name
+                );
+                package_changes
+                    .entry(&package.id)
+                    .or_default()
+                    .push(changed_file.clone());
+            }
+            let Ok(relative_path) = changed_file.strip_prefix(self.workspace_root) else {
+                continue;
+            };
+            for &package in &self.members {
+                if self.change_filters[&package.id]
+                    .include
+                    .is_match(relative_path)
+                {
+                    debug!(
+                        "File {} is included for package '{}'",
+                        changed_file.display(),
+                        package.name
+                    );
+                    package_changes
+                        .entry(&package.id)
+                        .or_default()
+                        .push(changed_file.clone());
+                }
+            }
+        }
+        package_changes
+    }
+}
+
+/// Add all workspace members depending on `affected` packages to it, transitively.
+/// Dev-dependencies are not followed, as they do not change the published artifact.
+/// Returns the name of the dependency each added package has been added for.
+pub fn propagate_to_dependents<'a>(
+    members: &[&'a Package],
+    affected: &mut Vec<&'a Package>,
+) -> BTreeMap<&'a PackageId, &'a str> {
+    let mut propagated_from = BTreeMap::new();
+    let mut i = 0;
+    while i < affected.len() {
+        let dependency = affected[i];
+        let dependency_dir = dependency
+            .manifest_path
+            .parent()
+            .expect("manifest path shall have a parent directory");
+        for &member in members {
+            if affected.iter().any(|p| p.id == member.id) {
+                continue;
+            }
+            let depends_on = member.dependencies.iter().any(|dep| {
+                dep.kind != DependencyKind::Development
+                    && dep.path.as_deref() == Some(dependency_dir)
+            });
+            if depends_on {
+                info!(
+                    "Package '{}' is affected as it depends on '{}'",
+                    member.name, dependency.name
+                );
+                affected.push(member);
+                propagated_from.insert(&member.id, dependency.name.as_str());
+            }
+        }
+        i += 1;
+    }
+    propagated_from
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, dir: &str, src_path: &str) -> Package {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "version": "0.1.0",
+            "id": format!("path+file://{}#{}@0.1.0", dir, name),
+            "dependencies": [],
+            "targets": [{
+                "name": name,
+                "kind": ["lib"],
+                "crate_types": ["lib"],
+                "required-features": [],
+                "src_path": format!("{}/{}", dir, src_path),
+            }],
+            "features": {},
+            "manifest_path": format!("{}/Cargo.toml", dir),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_dep_info() {
+        let content = "\
+/ws/target/debug/deps/a-0123.d: src/lib.rs src/my\\ mod.rs ../data.txt
+
+src/lib.rs:
+src/my\\ mod.rs:
+../data.txt:
+
+# env-dep:CARGO_PKG_NAME=a
+";
+        let files = parse_dep_info(content, Path::new("/ws/a"));
+        let expected = ["/ws/a/src/lib.rs", "/ws/a/src/my mod.rs", "/ws/data.txt"];
+        assert_eq!(
+            files,
+            expected.iter().map(PathBuf::from).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn attributes_dep_info_to_the_crate_root_only() {
+        let cli = package("cli", "/ws/crates/cli", "src/main.rs");
+        let core = package("core-x", "/ws/crates/core", "src/lib.rs");
+        let member_dirs = BTreeMap::from([
+            (Path::new("/ws/crates/cli"), &cli),
+            (Path::new("/ws/crates/core"), &core),
+        ]);
+        let files: Vec<_> = [
+            "/ws/crates/cli/src/main.rs",
+            "/ws/crates/core/src/lib.rs",
+            "/ws/data.txt",
+            "/elsewhere/std.rs",
+        ]
+        .iter()
+        .map(PathBuf::from)
+        .collect();
+        let (package, files) = attribute_dep_info(&files, Path::new("/ws"), &member_dirs).unwrap();
+        assert_eq!(package.name, "cli");
+        assert_eq!(
+            files,
+            [
+                PathBuf::from("/ws/crates/cli/src/main.rs"),
+                PathBuf::from("/ws/data.txt")
+            ]
+        );
+
+        let files = [PathBuf::from("/ws/other/src/lib.rs")];
+        assert!(attribute_dep_info(&files, Path::new("/ws"), &member_dirs).is_none());
+    }
+}
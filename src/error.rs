@@ -0,0 +1,57 @@
+//! Errors that map to distinct exit codes, so that scripts can branch on them.
+
+use std::fmt;
+use std::process::ExitCode;
+
+/// Exit code when nothing has to be bumped.
+pub const EXIT_NOTHING_TO_BUMP: u8 = 4;
+/// Exit code for invalid arguments, configuration or manifests, like clap's usage errors.
+pub const EXIT_BAD_INPUT: u8 = 2;
+/// Exit code when a git or cargo command fails.
+pub const EXIT_COMMAND_FAILED: u8 = 3;
+
+/// Invalid arguments, configuration or manifests.
+#[derive(Debug)]
+pub struct BadInput(pub String);
+
+impl fmt::Display for BadInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for BadInput {}
+
+/// Shorthand for a [`BadInput`] error.
+pub fn bad_input(message: impl fmt::Display) -> anyhow::Error {
+    BadInput(message.to_string()).into()
+}
+
+/// A git or cargo command which failed to run or exited unsuccessfully.
+#[derive(Debug)]
+pub struct CommandFailed {
+    pub command: String,
+    pub stderr: String,
+}
+
+impl fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` failed", self.command)?;
+        if !self.stderr.is_empty() {
+            write!(f, ": {}", self.stderr)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CommandFailed {}
+
+pub fn exit_code(err: &anyhow::Error) -> ExitCode {
+    if err.downcast_ref::<BadInput>().is_some() {
+        ExitCode::from(EXIT_BAD_INPUT)
+    } else if err.downcast_ref::<CommandFailed>().is_some() {
+        ExitCode::from(EXIT_COMMAND_FAILED)
+    } else {
+        ExitCode::FAILURE
+    }
+}
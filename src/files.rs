@@ -0,0 +1,72 @@
+//! All-or-nothing file writes.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use tracing::{debug, error};
+
+/// Write `content` to a temporary file next to `path` and rename it over `path`, so that
+/// `path` never holds partial content.
+fn write_atomic(path: &Path, content: &str) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .with_context(|| format!("{} is not a file path", path.display()))?;
+    let mut temp_name = file_name.to_os_string();
+    temp_name.push(".cargo-jump.tmp");
+    let temp_path = path.with_file_name(temp_name);
+    let result = std::fs::write(&temp_path, content)
+        .with_context(|| format!("cannot write {}", temp_path.display()))
+        .and_then(|()| match std::fs::metadata(path) {
+            Ok(metadata) => std::fs::set_permissions(&temp_path, metadata.permissions())
+                .with_context(|| format!("cannot set permissions of {}", temp_path.display())),
+            Err(_) => Ok(()),
+        })
+        .and_then(|()| {
+            std::fs::rename(&temp_path, path)
+                .with_context(|| format!("cannot replace {}", path.display()))
+        });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Files written so far with their original content, `None` for files which did not exist.
+#[derive(Default)]
+pub struct Transaction {
+    originals: Vec<(PathBuf, Option<String>)>,
+}
+
+impl Transaction {
+    pub fn write(&mut self, path: &Path, content: &str) -> Result<()> {
+        let original = if path.exists() {
+            Some(
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("cannot read {}", path.display()))?,
+            )
+        } else {
+            None
+        };
+        // A failed write leaves the file untouched, so there is nothing to restore
+        write_atomic(path, content)?;
+        self.originals.push((path.to_path_buf(), original));
+        Ok(())
+    }
+
+    /// Restore every file written so far. Failures are logged, as the original error
+    /// matters more.
+    pub fn rollback(self) {
+        for (path, original) in self.originals.into_iter().rev() {
+            debug!("Restoring {}", path.display());
+            let result = match original {
+                Some(original) => write_atomic(&path, &original),
+                None if path.exists() => std::fs::remove_file(&path)
+                    .with_context(|| format!("cannot remove {}", path.display())),
+                None => Ok(()),
+            };
+            if let Err(err) = result {
+                error!("Cannot restore {}: {:#}", path.display(), err);
+            }
+        }
+    }
+}
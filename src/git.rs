@@ -0,0 +1,282 @@
+//! Thin wrappers around git commands.
+
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use anyhow::Result;
+use tracing::debug;
+
+use crate::error::CommandFailed;
+
+/// Run git in `dir`, leaving the interpretation of its exit status to the caller.
+fn git_output<S: AsRef<OsStr>>(
+    dir: &Path,
+    args: impl IntoIterator<Item = S>,
+) -> Result<(String, Output)> {
+    let args: Vec<OsString> = args.into_iter().map(|it| it.as_ref().to_owned()).collect();
+    let command = format!(
+        "git {}",
+        args.iter()
+            .map(|it| it.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(&args)
+        .output()
+        .map_err(|err| CommandFailed {
+            command: command.clone(),
+            stderr: err.to_string(),
+        })?;
+    Ok((command, output))
+}
+
+/// Run git in `dir`, returning its stdout, or failing with its stderr.
+fn git<S: AsRef<OsStr>>(dir: &Path, args: impl IntoIterator<Item = S>) -> Result<String> {
+    let (command, output) = git_output(dir, args)?;
+    if !output.status.success() {
+        return Err(CommandFailed {
+            command,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }
+        .into());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+pub fn git_toplevel(dir: &Path) -> Result<PathBuf> {
+    let path = git(dir, ["rev-parse", "--show-toplevel"])?
+        .trim()
+        .to_string();
+    Ok(PathBuf::from(path))
+}
+
+pub fn git_rev_exists(toplevel: &Path, rev: &str) -> Result<bool> {
+    let (_, output) = git_output(
+        toplevel,
+        [
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("{}^{{commit}}", rev),
+        ],
+    )?;
+    Ok(output.status.success())
+}
+
+/// Find the most recent tag reachable from `rev` matching a glob pattern.
+pub fn git_latest_tag(toplevel: &Path, pattern: &str, rev: &str) -> Result<Option<String>> {
+    let (command, output) = git_output(
+        toplevel,
+        ["describe", "--tags", "--abbrev=0", "--match", pattern, rev],
+    )?;
+
+    if !output.status.success() {
+        debug!(
+            "{} failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return Ok(None);
+    }
+
+    Ok(Some(String::from_utf8(output.stdout)?.trim().to_string()))
+}
+
+pub struct GitCommit {
+    pub message: String,
+    pub files: Vec<PathBuf>,
+}
+
+/// List commits since `base` (or all commits if `None`) up to `to` with the files they
+/// changed.
+pub fn git_log(toplevel: &Path, base: Option<&str>, to: &str) -> Result<Vec<GitCommit>> {
+    let range = match base {
+        Some(base) => format!("{}..{}", base, to),
+        None => to.to_string(),
+    };
+    // Each commit is a record separator, its message, a unit separator and its files
+    git(
+        toplevel,
+        [
+            "log",
+            "--format=%x1e%B%x1f",
+            "--name-status",
+            "-M",
+            "-z",
+            &range,
+        ],
+    )?
+    .split('\x1e')
+    .skip(1)
+    .map(|record| {
+        let Some((message, files)) = record.split_once('\x1f') else {
+            anyhow::bail!("Unexpected git log output");
+        };
+        Ok(GitCommit {
+            message: message.trim().to_string(),
+            files: parse_name_status(toplevel, files)?,
+        })
+    })
+    .collect()
+}
+
+/// List tracked files with uncommitted changes.
+pub fn git_dirty_files(toplevel: &Path) -> Result<Vec<PathBuf>> {
+    Ok(git(toplevel, ["diff", "HEAD", "--name-only"])?
+        .lines()
+        .map(|line| toplevel.join(line))
+        .collect())
+}
+
+pub fn git_is_tracked(toplevel: &Path, file: &Path) -> Result<bool> {
+    let (_, output) = git_output(
+        toplevel,
+        [
+            OsStr::new("ls-files"),
+            OsStr::new("--error-unmatch"),
+            OsStr::new("--"),
+            file.as_os_str(),
+        ],
+    )?;
+    Ok(output.status.success())
+}
+
+/// Commit exactly `files`, leaving anything else in the index alone.
+pub fn git_commit(toplevel: &Path, files: &[PathBuf], message: &str) -> Result<()> {
+    // New files, e.g. changelogs, have to be known to git before committing them by path
+    git(
+        toplevel,
+        ["add", "--"]
+            .iter()
+            .map(OsStr::new)
+            .chain(files.iter().map(|it| it.as_os_str())),
+    )?;
+    git(
+        toplevel,
+        ["commit", "--quiet", "-m", message, "--"]
+            .iter()
+            .map(OsStr::new)
+            .chain(files.iter().map(|it| it.as_os_str())),
+    )?;
+    Ok(())
+}
+
+pub fn git_tag(toplevel: &Path, tag: &str, message: &str) -> Result<()> {
+    git(toplevel, ["tag", "--annotate", "-m", message, tag])?;
+    Ok(())
+}
+
+/// Parse `--name-status -z` output into the files it mentions. Renamed files count with
+/// both their old and new paths, so that moving a file out of a package changes it too.
+fn parse_name_status(toplevel: &Path, output: &str) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut fields = output.split('\0');
+    while let Some(status) = fields.next() {
+        let status = status.trim_start_matches('\n');
+        if status.is_empty() {
+            continue;
+        }
+        let Some(path) = fields.next() else {
+            anyhow::bail!("Unexpected git name-status output");
+        };
+        // Renames and copies are followed by the new path
+        let new_path = if status.starts_with(['R', 'C']) {
+            let Some(new_path) = fields.next() else {
+                anyhow::bail!("Unexpected git name-status output");
+            };
+            Some(new_path)
+        } else {
+            None
+        };
+        match new_path {
+            Some(new_path) if status.starts_with('C') => files.push(toplevel.join(new_path)),
+            Some(new_path) => {
+                files.push(toplevel.join(path));
+                files.push(toplevel.join(new_path));
+            }
+            None => files.push(toplevel.join(path)),
+        }
+    }
+    Ok(files)
+}
+
+/// List files changed since `old_tag` at `to`, or in the working tree and index if `None`.
+pub fn git_changed_files(toplevel: &Path, old_tag: &str, to: Option<&str>) -> Result<Vec<PathBuf>> {
+    let mut args = vec!["diff", "--name-status", "-M", "-z", old_tag];
+    args.extend(to);
+    parse_name_status(toplevel, &git(toplevel, args)?)
+}
+
+/// List untracked files, except ignored ones.
+pub fn git_untracked_files(toplevel: &Path) -> Result<Vec<PathBuf>> {
+    Ok(
+        git(toplevel, ["ls-files", "--others", "--exclude-standard"])?
+            .lines()
+            .map(|line| toplevel.join(line))
+            .collect(),
+    )
+}
+
+/// List files at `rev`, or in the index if `None`.
+pub fn git_all_files(toplevel: &Path, rev: Option<&str>) -> Result<Vec<PathBuf>> {
+    let output = match rev {
+        Some(rev) => git(toplevel, ["ls-tree", "-r", "--name-only", rev])?,
+        None => git(toplevel, ["ls-files"])?,
+    };
+    Ok(output.lines().map(|line| toplevel.join(line)).collect())
+}
+
+/// Find the best common ancestor of two revisions.
+pub fn git_merge_base(toplevel: &Path, a: &str, b: &str) -> Result<String> {
+    Ok(git(toplevel, ["merge-base", a, b])?.trim().to_string())
+}
+
+/// Read `file` at `rev`, `None` if it does not exist there.
+pub fn git_show(toplevel: &Path, rev: &str, file: &Path) -> Result<Option<String>> {
+    let path = file.strip_prefix(toplevel).unwrap_or(file);
+    let object = format!("{}:{}", rev, path.to_string_lossy());
+    let (_, output) = git_output(toplevel, ["cat-file", "-e", &object])?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(Some(git(toplevel, ["show", &object])?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_name_status() {
+        let output =
+            "M\0a/src/lib.rs\0R100\0a/src/old.rs\0b/src/new.rs\0C075\0a/x.rs\0c/x.rs\0D\0d.rs\0";
+        let files = parse_name_status(Path::new("/repo"), output).unwrap();
+        let expected = [
+            "a/src/lib.rs",
+            "a/src/old.rs",
+            "b/src/new.rs",
+            "c/x.rs",
+            "d.rs",
+        ];
+        assert_eq!(
+            files,
+            expected
+                .iter()
+                .map(|it| Path::new("/repo").join(it))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parses_log_name_status() {
+        // Like the files of a commit in `git log -z`, which follow a newline
+        let output = "\nM\0a.rs\0\nA\0b.rs\0";
+        let files = parse_name_status(Path::new("/repo"), output).unwrap();
+        assert_eq!(files, [Path::new("/repo/a.rs"), Path::new("/repo/b.rs")]);
+        assert!(parse_name_status(Path::new("/repo"), "R100\0old.rs").is_err());
+    }
+}
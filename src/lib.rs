@@ -0,0 +1,21 @@
+//! Bump versions in a Cargo workspace based on changed files.
+//!
+//! [`Workspace::detect_affected`] finds the members changed since their last release,
+//! [`Workspace::plan_bump`] computes their new versions and the resulting file contents, and
+//! [`Workspace::apply_plan`] writes them.
+
+pub mod bump;
+mod changelog;
+pub mod config;
+pub mod conventional;
+mod detect;
+pub mod error;
+mod files;
+mod git;
+mod manifest;
+mod manifest_diff;
+pub mod plan;
+mod release;
+mod workspace;
+
+pub use workspace::{AffectedPackage, BumpOptions, DetectOptions, Workspace};
@@ -1,10 +1,15 @@
-use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-use anyhow::Result;
-use cargo_metadata::MetadataCommand;
+use anyhow::{Context, Result};
+use cargo_jump::bump::{BumpTarget, parse_bump_target};
+use cargo_jump::config::VersionScheme;
+use cargo_jump::error::{EXIT_NOTHING_TO_BUMP, bad_input, exit_code};
+use cargo_jump::plan::{OutputFormat, Plan};
+use cargo_jump::{BumpOptions, DetectOptions, Workspace};
 use clap::Parser;
-use toml_edit::DocumentMut;
-use tracing::{debug, info, warn};
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::filter::LevelFilter;
 
 #[derive(Parser)]
 #[command(name = "cargo")]
@@ -16,161 +21,279 @@ enum CargoCli {
 #[derive(clap::Args)]
 #[command(version, about, long_about = None)]
 struct JumpArgs {
-    /// New version to set
-    new_version: String,
+    /// New version to set, a bump level (major, minor, patch, prerelease, release), or
+    /// `auto` to derive the level from Conventional Commits. Optional with the calver version
+    /// scheme.
+    #[arg(value_name = "VERSION|LEVEL", value_parser = parse_bump_target)]
+    new_version: Option<BumpTarget>,
 
-    /// Old git tag for comparison
+    /// Revision to compare against, e.g. a tag, branch or commit. A plain version gets the
+    /// tag prefix prepended if needed, e.g. `1.2.3` -> `v1.2.3`. A range `A..B` also sets
+    /// `--to`, and `A...B` compares `B` against the merge base of `A` and `B`.
+    #[arg(long, visible_alias = "old-tag", value_name = "REV")]
+    from: Option<String>,
+
+    /// Revision to detect changes at, HEAD by default
+    #[arg(long, value_name = "REV")]
+    to: Option<String>,
+
+    /// Also consider staged and unstaged changes, comparing the old tag against the working
+    /// tree instead of HEAD
+    #[arg(long)]
+    include_worktree: bool,
+
+    /// Also consider untracked files, implies `--include-worktree`
     #[arg(long)]
-    old_tag: Option<String>,
+    include_untracked: bool,
+
+    /// Only bump these packages among the affected ones, glob patterns allowed
+    #[arg(short, long = "package", value_name = "SPEC")]
+    package: Vec<String>,
+
+    /// Consider all workspace members, the default
+    #[arg(long, conflicts_with = "package")]
+    workspace: bool,
+
+    /// Never bump these packages, glob patterns allowed
+    #[arg(long, value_name = "SPEC")]
+    exclude: Vec<String>,
+
+    /// Bump these packages even if they are not affected, glob patterns allowed
+    #[arg(long, value_name = "SPEC")]
+    force: Vec<String>,
 
     /// Don't modify anything
     #[arg(long)]
     dry_run: bool,
-}
 
-fn git_toplevel() -> Result<PathBuf> {
-    let output = std::process::Command::new("git")
-        .args(["rev-parse", "--show-toplevel"])
-        .output()?;
+    /// Allow setting a version lower than or equal to the current one
+    #[arg(long)]
+    allow_downgrade: bool,
 
-    if !output.status.success() {
-        anyhow::bail!("Failed to get git toplevel directory");
-    }
+    /// Also bump workspace members depending on affected packages, transitively
+    #[arg(long, overrides_with = "no_propagate")]
+    propagate: bool,
 
-    let path = String::from_utf8(output.stdout)?.trim().to_string();
-    Ok(PathBuf::from(path))
-}
+    /// Don't bump dependents of affected packages, even if configured
+    #[arg(long, overrides_with = "propagate")]
+    no_propagate: bool,
 
-fn git_changed_files(toplevel: &Path, old_tag: &str) -> Result<Vec<PathBuf>> {
-    let output = std::process::Command::new("git")
-        .args([
-            "-C",
-            toplevel
-                .as_os_str()
-                .to_str()
-                .expect("shall be a valid UTF-8 path"),
-        ])
-        .args(["diff", "--name-only", old_tag, "HEAD"])
-        .output()?;
-
-    if !output.status.success() {
-        anyhow::bail!("Failed to get changed files from git");
-    }
+    /// Prefix of release tags, overriding `tag-prefix` in the config
+    #[arg(long)]
+    tag_prefix: Option<String>,
 
-    Ok(String::from_utf8(output.stdout)?
-        .lines()
-        .map(|line| toplevel.join(line))
-        .collect())
-}
+    /// Glob pattern of release tags to find the old tag with when `--old-tag` is not given,
+    /// overriding `tag-pattern` in the config. `{name}` is replaced by each package name.
+    #[arg(long)]
+    tag_pattern: Option<String>,
 
-fn git_all_files(toplevel: &Path) -> Result<Vec<PathBuf>> {
-    let output = std::process::Command::new("git")
-        .args([
-            "-C",
-            toplevel
-                .as_os_str()
-                .to_str()
-                .expect("shall be a valid UTF-8 path"),
-        ])
-        .args(["ls-files"])
-        .output()?;
-
-    if !output.status.success() {
-        anyhow::bail!("Failed to get all files from git");
-    }
+    /// Compare each package against its own `{name}-v{version}` tag, overriding
+    /// `independent` in the config
+    #[arg(long, overrides_with = "no_independent")]
+    independent: bool,
+
+    /// Compare all packages against the same tag, even if configured otherwise
+    #[arg(long, overrides_with = "independent")]
+    no_independent: bool,
+
+    /// Files of each package to ignore, overriding `ignore` in the workspace config
+    #[arg(long, value_name = "GLOB")]
+    ignore: Option<Vec<String>>,
+
+    /// Also consider files listed in dep-info files of a previous build, e.g. those included
+    /// with `include_str!`, overriding `dep-info` in the config
+    #[arg(long, overrides_with = "no_dep_info")]
+    dep_info: bool,
+
+    /// Don't read dep-info files, even if configured
+    #[arg(long, overrides_with = "dep_info")]
+    no_dep_info: bool,
+
+    /// Also bump members whose resolved dependencies changed in `Cargo.lock`, overriding
+    /// `lockfile-changes` in the config
+    #[arg(long, overrides_with = "no_lockfile_changes")]
+    lockfile_changes: bool,
+
+    /// Ignore changes of `Cargo.lock`, even if configured otherwise
+    #[arg(long, overrides_with = "lockfile_changes")]
+    no_lockfile_changes: bool,
+
+    /// Version scheme, overriding `version-scheme` in the config
+    #[arg(long, value_enum)]
+    version_scheme: Option<VersionScheme>,
+
+    /// Add a section for the new version to the changelog of each bumped package, overriding
+    /// `changelog` in the config
+    #[arg(long, overrides_with = "no_changelog")]
+    changelog: bool,
+
+    /// Don't update changelogs, even if configured
+    #[arg(long, overrides_with = "changelog")]
+    no_changelog: bool,
+
+    /// Commit the modified files
+    #[arg(long)]
+    commit: bool,
+
+    /// Tag the release commit, with one workspace tag, or per-package tags in independent mode
+    #[arg(long, requires = "commit")]
+    tag: bool,
+
+    /// Print the plan of bumped packages and modified files to stdout in this format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
 
-    Ok(String::from_utf8(output.stdout)?
-        .lines()
-        .map(|line| toplevel.join(line))
-        .collect())
+    /// Print the effective configuration and exit
+    #[arg(long)]
+    show_config: bool,
+}
+
+/// How a successful run ended.
+enum Outcome {
+    Done,
+    NothingToBump,
 }
 
-fn main() {
-    tracing_subscriber::fmt::init();
+fn main() -> ExitCode {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(
+            EnvFilter::builder()
+                .with_default_directive(LevelFilter::INFO.into())
+                .from_env_lossy(),
+        )
+        .init();
     let CargoCli::Jump(args) = CargoCli::parse();
-    let metadata = MetadataCommand::new()
-        .no_deps()
-        .exec()
-        .expect("cannot get cargo metadata");
-    let toplevel = git_toplevel().expect("cannot get git toplevel directory");
-
-    if !metadata.workspace_root.starts_with(&toplevel) {
-        panic!("workspace root is not inside git toplevel");
+    match run(args) {
+        Ok(Outcome::Done) => ExitCode::SUCCESS,
+        Ok(Outcome::NothingToBump) => ExitCode::from(EXIT_NOTHING_TO_BUMP),
+        Err(err) => {
+            error!("{:#}", err);
+            exit_code(&err)
+        }
     }
+}
 
-    let changed_files = if let Some(old_tag) = &args.old_tag {
-        git_changed_files(&toplevel, old_tag).expect("cannot get changed files from git")
-    } else {
-        warn!("old_tag not provided, considering all files as changed");
-        git_all_files(&toplevel).expect("cannot get all files from git")
+fn run(args: JumpArgs) -> Result<Outcome> {
+    let mut workspace = Workspace::load(None)?;
+    let config = &mut workspace.config;
+    if let Some(tag_prefix) = &args.tag_prefix {
+        config.workspace.tag_prefix = tag_prefix.clone();
+    }
+    if let Some(tag_pattern) = &args.tag_pattern {
+        config.workspace.tag_pattern = Some(tag_pattern.clone());
+    }
+    if args.independent {
+        config.workspace.independent = true;
+    } else if args.no_independent {
+        config.workspace.independent = false;
+    }
+    if args.changelog {
+        config.workspace.changelog = true;
+    } else if args.no_changelog {
+        config.workspace.changelog = false;
+    }
+    if let Some(ignore) = &args.ignore {
+        config.workspace.ignore = ignore.clone();
+    }
+    if args.dep_info {
+        config.workspace.dep_info = true;
+    } else if args.no_dep_info {
+        config.workspace.dep_info = false;
+    }
+    if args.lockfile_changes {
+        config.workspace.lockfile_changes = true;
+    } else if args.no_lockfile_changes {
+        config.workspace.lockfile_changes = false;
+    }
+    if let Some(version_scheme) = args.version_scheme {
+        config.workspace.version_scheme = version_scheme;
+    }
+    if args.propagate {
+        config.workspace.propagate = true;
+    } else if args.no_propagate {
+        config.workspace.propagate = false;
+    }
+
+    if args.show_config {
+        print!(
+            "{}",
+            workspace
+                .config
+                .to_toml(&workspace.members())
+                .context("cannot serialize configuration")?
+        );
+        return Ok(Outcome::Done);
+    }
+
+    let bump_target = match (&args.new_version, workspace.config.workspace.version_scheme) {
+        (Some(bump_target), _) => bump_target.clone(),
+        (None, VersionScheme::Calver) => BumpTarget::Calendar,
+        (None, VersionScheme::Semver) => {
+            return Err(bad_input("a version or bump level is required"));
+        }
     };
 
-    let workspace_member_ids = metadata.workspace_members;
-    let members: Vec<_> = metadata
-        .packages
-        .iter()
-        .filter(|p| workspace_member_ids.contains(&p.id))
-        .collect();
-
-    let mut all_affected_packages = Vec::new();
-    for package in members {
-        let manifest_path = package.manifest_path.as_std_path();
-        let manifest_dir = manifest_path
-            .parent()
-            .expect("manifest path shall have a parent directory");
-        let is_affected = changed_files
-            .iter()
-            .any(|changed_file| changed_file.starts_with(manifest_dir));
-        if is_affected {
-            debug!("Package '{}' is affected", package.name);
-            all_affected_packages.push(package);
-        } else {
-            debug!("Package '{}' is not affected", package.name);
+    if args.commit {
+        let dirty_files = workspace.dirty_files()?;
+        if !dirty_files.is_empty() {
+            return Err(bad_input(format!(
+                "refusing to commit with uncommitted changes in: {}",
+                dirty_files
+                    .iter()
+                    .map(|it| it.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
         }
     }
 
-    if all_affected_packages.is_empty() {
+    let detect_options = DetectOptions {
+        from: args.from.clone(),
+        to: args.to.clone(),
+        include_worktree: args.include_worktree,
+        include_untracked: args.include_untracked,
+        packages: args.package.clone(),
+        exclude: args.exclude.clone(),
+        force: args.force.clone(),
+    };
+    let affected = workspace.detect_affected(&detect_options)?;
+    if affected.is_empty() {
         info!("No affected packages found.");
-        return;
+        Plan::default()
+            .print(args.format)
+            .context("cannot print plan")?;
+        return Ok(Outcome::NothingToBump);
     }
 
-    let mut has_change = false;
+    let options = BumpOptions {
+        target: bump_target,
+        tag: args.tag,
+        allow_downgrade: args.allow_downgrade,
+    };
+    let plan = workspace.plan_bump(&affected, &options)?;
+    plan.print(args.format).context("cannot print plan")?;
 
-    for package in all_affected_packages {
-        info!(
-            "Setting version of package '{}' to '{}'",
-            package.name, args.new_version
-        );
-        let manifest_path = package.manifest_path.as_std_path();
-        let mut manifest_content: DocumentMut = std::fs::read_to_string(manifest_path)
-            .expect("cannot read manifest file")
-            .parse()
-            .expect("cannot parse manifest file as TOML document");
-        let package_table = manifest_content
-            .get_mut("package")
-            .and_then(|it| it.as_table_mut())
-            .expect("missing [package]");
-        let version_item = package_table
-            .get_mut("version")
-            .expect("missing package.version");
-        *version_item = toml_edit::value(args.new_version.clone());
-        if args.dry_run {
-            info!("Dry run: not updating {}", manifest_path.display());
-        } else {
-            std::fs::write(manifest_path, manifest_content.to_string())
-                .expect("cannot write updated manifest file");
-            has_change = true;
+    if args.dry_run {
+        for path in &plan.files {
+            info!("Dry run: not updating {}", path.display());
         }
+    } else {
+        workspace.apply_plan(&plan)?;
     }
 
-    if has_change {
-        info!("Updating Cargo.lock...");
-        let output = std::process::Command::new("cargo")
-            .args(["fetch"])
-            .output()
-            .expect("failed to execute cargo fetch");
-        if !output.status.success() {
-            panic!("cargo fetch failed");
+    if args.commit {
+        if args.dry_run {
+            info!(
+                "Dry run: not committing with message:\n{}",
+                plan.commit_message
+            );
+            for tag in &plan.tags {
+                info!("Dry run: not creating tag '{}'", tag.name);
+            }
+        } else {
+            workspace.commit_plan(&plan)?;
         }
     }
+    Ok(Outcome::Done)
 }
@@ -0,0 +1,298 @@
+//! Editing of manifests and `Cargo.lock`.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use cargo_metadata::semver::Version;
+use toml_edit::{DocumentMut, TableLike};
+use tracing::{info, warn};
+
+use crate::error::bad_input;
+
+pub fn read_toml_document(path: &Path) -> Result<DocumentMut> {
+    let content =
+        std::fs::read_to_string(path).with_context(|| format!("cannot read {}", path.display()))?;
+    content
+        .parse()
+        .map_err(|err| bad_input(format!("cannot parse {}: {}", path.display(), err)))
+}
+
+/// Whether the manifest declares `version.workspace = true`.
+pub fn inherits_workspace_version(manifest: &DocumentMut) -> bool {
+    manifest
+        .get("package")
+        .and_then(|it| it.get("version"))
+        .and_then(|it| it.get("workspace"))
+        .and_then(|it| it.as_bool())
+        .unwrap_or(false)
+}
+
+pub(crate) const DEPENDENCY_TABLES: [&str; 3] =
+    ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Collect `[dependencies]`-like tables of a manifest, including target-specific ones and
+/// `[workspace.dependencies]`.
+fn dependency_tables_mut(manifest: &mut DocumentMut) -> Vec<&mut dyn TableLike> {
+    let mut tables = Vec::new();
+    for (key, item) in manifest.as_table_mut().iter_mut() {
+        match key.get() {
+            key if DEPENDENCY_TABLES.contains(&key) => tables.extend(item.as_table_like_mut()),
+            "target" => {
+                let Some(targets) = item.as_table_like_mut() else {
+                    continue;
+                };
+                for (_, target) in targets.iter_mut() {
+                    let Some(target) = target.as_table_like_mut() else {
+                        continue;
+                    };
+                    for (key, item) in target.iter_mut() {
+                        if DEPENDENCY_TABLES.contains(&key.get()) {
+                            tables.extend(item.as_table_like_mut());
+                        }
+                    }
+                }
+            }
+            "workspace" => tables.extend(
+                item.get_mut("dependencies")
+                    .and_then(|it| it.as_table_like_mut()),
+            ),
+            _ => {}
+        }
+    }
+    tables
+}
+
+/// Rewrite a version requirement to match `version`, preserving its operator and precision,
+/// e.g. `~0.1` -> `~0.2` and `=0.1.0` -> `=0.2.0`.
+/// Returns `None` for requirements that cannot be rewritten this way, e.g. `>=0.1, <0.3`.
+fn update_version_req(req: &str, version: &Version) -> Option<String> {
+    let req = req.trim();
+    let digits_start = req.find(|c: char| c.is_ascii_digit())?;
+    let (op, old_version) = req.split_at(digits_start);
+    if !matches!(op.trim(), "" | "^" | "=" | "~" | ">=") || old_version.contains([',', '*']) {
+        return None;
+    }
+    let precision = old_version
+        .split(['-', '+'])
+        .next()
+        .expect("split shall yield at least one item")
+        .split('.')
+        .count();
+    let new_version = match precision {
+        _ if !version.pre.is_empty() => version.to_string(),
+        1 => version.major.to_string(),
+        2 => format!("{}.{}", version.major, version.minor),
+        _ => version.to_string(),
+    };
+    Some(format!("{}{}", op, new_version))
+}
+
+/// Rewrite the version requirements of path dependencies on bumped packages.
+/// Returns whether the manifest has been modified.
+pub fn update_path_dependencies(
+    manifest_path: &Path,
+    manifest: &mut DocumentMut,
+    new_versions: &BTreeMap<String, String>,
+) -> bool {
+    let mut modified = false;
+    for table in dependency_tables_mut(manifest) {
+        for (key, dependency) in table.iter_mut() {
+            let Some(dependency) = dependency.as_table_like_mut() else {
+                continue;
+            };
+            if !dependency.contains_key("path") {
+                continue;
+            }
+            let name = dependency
+                .get("package")
+                .and_then(|it| it.as_str())
+                .unwrap_or(key.get())
+                .to_string();
+            let Some(new_version) = new_versions.get(&name) else {
+                continue;
+            };
+            let Some(req) = dependency
+                .get_mut("version")
+                .and_then(|it| it.as_value_mut())
+            else {
+                continue;
+            };
+            let Some(old_req) = req.as_str().map(|it| it.to_string()) else {
+                continue;
+            };
+            let Ok(new_version) = new_version.parse::<Version>() else {
+                warn!(
+                    "Cannot update requirement on '{}' in {}: '{}' is not a valid version",
+                    name,
+                    manifest_path.display(),
+                    new_version
+                );
+                continue;
+            };
+            let Some(new_req) = update_version_req(&old_req, &new_version) else {
+                warn!(
+                    "Cannot update requirement '{}' on '{}' in {}, please update it manually",
+                    old_req,
+                    name,
+                    manifest_path.display()
+                );
+                continue;
+            };
+            if new_req == old_req {
+                continue;
+            }
+            info!(
+                "Updating requirement on '{}' in {} from '{}' to '{}'",
+                name,
+                manifest_path.display(),
+                old_req,
+                new_req
+            );
+            let decor = req.decor().clone();
+            *req = new_req.into();
+            *req.decor_mut() = decor;
+            modified = true;
+        }
+    }
+    modified
+}
+
+/// Update the versions of bumped workspace members in `Cargo.lock`, without resolving
+/// anything else. `bumped_versions` maps package names to their old and new versions.
+/// Returns whether the lockfile has been modified.
+pub fn update_lockfile(
+    lockfile: &mut DocumentMut,
+    bumped_versions: &BTreeMap<String, (String, String)>,
+) -> bool {
+    let Some(packages) = lockfile
+        .get_mut("package")
+        .and_then(|it| it.as_array_of_tables_mut())
+    else {
+        return false;
+    };
+    let mut modified = false;
+    for package in packages.iter_mut() {
+        // Workspace members have no source
+        if package.contains_key("source") {
+            continue;
+        }
+        let Some(name) = package.get("name").and_then(|it| it.as_str()) else {
+            continue;
+        };
+        let Some((old_version, new_version)) = bumped_versions.get(name) else {
+            continue;
+        };
+        let Some(version) = package.get_mut("version").and_then(|it| it.as_value_mut()) else {
+            continue;
+        };
+        if version.as_str() != Some(old_version) {
+            continue;
+        }
+        let decor = version.decor().clone();
+        *version = new_version.as_str().into();
+        *version.decor_mut() = decor;
+        modified = true;
+    }
+    // Dependencies are written as "name version" when the name alone is ambiguous
+    for package in packages.iter_mut() {
+        let Some(dependencies) = package
+            .get_mut("dependencies")
+            .and_then(|it| it.as_array_mut())
+        else {
+            continue;
+        };
+        for dependency in dependencies.iter_mut() {
+            let Some((name, version)) = dependency.as_str().and_then(|it| it.split_once(' '))
+            else {
+                continue;
+            };
+            let Some((old_version, new_version)) = bumped_versions.get(name) else {
+                continue;
+            };
+            if version != old_version {
+                continue;
+            }
+            let decor = dependency.decor().clone();
+            *dependency = format!("{} {}", name, new_version).into();
+            *dependency.decor_mut() = decor;
+            modified = true;
+        }
+    }
+    modified
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn updates_lockfile() {
+        let lockfile = r#"version = 4
+
+[[package]]
+name = "a"
+version = "0.1.0"
+
+[[package]]
+name = "a"
+version = "0.1.0"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "0000"
+
+[[package]]
+name = "b"
+version = "2.0.0"
+dependencies = [
+ "a 0.1.0",
+ "a 0.1.0 (registry+https://github.com/rust-lang/crates.io-index)",
+ "c",
+]
+
+[[package]]
+name = "c"
+version = "1.0.0"
+"#;
+        let mut document: DocumentMut = lockfile.parse().unwrap();
+        let bumped_versions = BTreeMap::from([
+            ("a".to_string(), ("0.1.0".to_string(), "0.2.0".to_string())),
+            ("b".to_string(), ("2.0.0".to_string(), "2.0.1".to_string())),
+        ]);
+        assert!(update_lockfile(&mut document, &bumped_versions));
+        let expected = lockfile
+            .replacen("version = \"0.1.0\"", "version = \"0.2.0\"", 1)
+            .replace("version = \"2.0.0\"", "version = \"2.0.1\"")
+            .replace(" \"a 0.1.0\",", " \"a 0.2.0\",");
+        assert_eq!(document.to_string(), expected);
+
+        let bumped_versions =
+            BTreeMap::from([("c".to_string(), ("0.9.0".to_string(), "1.1.0".to_string()))]);
+        assert!(!update_lockfile(&mut document, &bumped_versions));
+    }
+
+    #[test]
+    fn updates_version_requirements() {
+        let cases = [
+            ("0.1", "0.2.0", Some("0.2")),
+            ("~0.1", "0.2.0", Some("~0.2")),
+            ("^1", "2.0.0", Some("^2")),
+            ("=0.1.0", "0.2.0", Some("=0.2.0")),
+            (">= 0.1.0", "0.2.0", Some(">= 0.2.0")),
+            ("0.1", "0.2.0-alpha.1", Some("0.2.0-alpha.1")),
+            ("0.1.0-alpha.1", "0.1.0-alpha.2", Some("0.1.0-alpha.2")),
+            (">=0.1, <0.3", "0.2.0", None),
+            ("0.*", "0.2.0", None),
+            ("<0.3", "0.2.0", None),
+            ("*", "0.2.0", None),
+        ];
+        for (req, version, expected) in cases {
+            assert_eq!(
+                update_version_req(req, &version.parse().unwrap()).as_deref(),
+                expected,
+                "{} -> {}",
+                req,
+                version
+            );
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Semantic comparison of manifests and lockfiles between two revisions.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::manifest::DEPENDENCY_TABLES;
+
+/// Top-level keys of the root manifest which concern the whole workspace rather than a
+/// root package.
+const WORKSPACE_KEYS: [&str; 4] = ["workspace", "patch", "profile", "replace"];
+
+/// Parse a manifest or lockfile, so that formatting, comments and key order do not matter
+/// when comparing it.
+pub fn parse(content: &str) -> Result<Value> {
+    Ok(toml_edit::de::from_str(content)?)
+}
+
+/// Remove the tables or fields at dotted `paths`, e.g. `package.metadata`, so that they do
+/// not count as changes.
+pub fn without(value: &Value, paths: &[String]) -> Value {
+    let mut value = value.clone();
+    for path in paths {
+        let mut keys: Vec<_> = path.split('.').collect();
+        let Some(last) = keys.pop() else {
+            continue;
+        };
+        if let Some(table) = keys
+            .iter()
+            .try_fold(&mut value, |value, key| value.get_mut(*key))
+            .and_then(Value::as_object_mut)
+        {
+            table.remove(last);
+        }
+    }
+    value
+}
+
+fn get<'a>(value: &'a Value, path: &[&str]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |value, key| value.get(key))
+}
+
+/// Keys of the table at `path` whose values differ between `old` and `new`.
+fn changed_keys(old: &Value, new: &Value, path: &[&str]) -> BTreeSet<String> {
+    let empty = serde_json::Map::new();
+    let old = get(old, path).and_then(Value::as_object).unwrap_or(&empty);
+    let new = get(new, path).and_then(Value::as_object).unwrap_or(&empty);
+    old.keys()
+        .chain(new.keys())
+        .filter(|key| old.get(*key) != new.get(*key))
+        .cloned()
+        .collect()
+}
+
+/// Changes of the root manifest which may concern members.
+#[derive(Debug, Default)]
+pub struct WorkspaceChanges {
+    /// Changed fields of `[workspace.package]`
+    pub package_fields: BTreeSet<String>,
+    /// Changed entries of `[workspace.dependencies]`
+    pub dependencies: BTreeSet<String>,
+    /// Whether anything outside the workspace-wide sections changed, i.e. the root package
+    pub root_package: bool,
+}
+
+impl WorkspaceChanges {
+    /// Compare two root manifests, ignoring the `ignored` fields of a root package.
+    pub fn between(old: &Value, new: &Value, ignored: &[String]) -> Self {
+        let without_workspace_keys = |value: &Value| {
+            let mut value = without(value, ignored);
+            if let Some(table) = value.as_object_mut() {
+                table.retain(|key, _| !WORKSPACE_KEYS.contains(&key.as_str()));
+            }
+            value
+        };
+        Self {
+            package_fields: changed_keys(old, new, &["workspace", "package"]),
+            dependencies: changed_keys(old, new, &["workspace", "dependencies"]),
+            root_package: without_workspace_keys(old) != without_workspace_keys(new),
+        }
+    }
+
+    /// Changed workspace fields and dependencies a member manifest inherits, e.g.
+    /// `package.edition` or `dependencies.serde`.
+    pub fn inherited_by(&self, manifest: &Value) -> Vec<String> {
+        let inherits = |value: Option<&Value>| {
+            value
+                .and_then(|it| it.get("workspace"))
+                .and_then(Value::as_bool)
+                .unwrap_or(false)
+        };
+        let mut inherited = Vec::new();
+        for field in &self.package_fields {
+            if inherits(get(manifest, &["package", field])) {
+                inherited.push(format!("package.{}", field));
+            }
+        }
+        for (table_path, table) in dependency_tables(manifest) {
+            for dependency in &self.dependencies {
+                if inherits(table.get(dependency)) {
+                    inherited.push(format!("{}.{}", table_path, dependency));
+                }
+            }
+        }
+        inherited
+    }
+}
+
+/// `[dependencies]`-like tables of a manifest, including target-specific ones, along with
+/// their paths.
+fn dependency_tables(manifest: &Value) -> Vec<(String, &Value)> {
+    let mut tables = Vec::new();
+    for table in DEPENDENCY_TABLES {
+        if let Some(value) = manifest.get(table) {
+            tables.push((table.to_string(), value));
+        }
+    }
+    if let Some(targets) = manifest.get("target").and_then(Value::as_object) {
+        for (target, value) in targets {
+            for table in DEPENDENCY_TABLES {
+                if let Some(value) = value.get(table) {
+                    tables.push((format!("target.{}.{}", target, table), value));
+                }
+            }
+        }
+    }
+    tables
+}
+
+/// Key of a lockfile package, and how dependencies refer to it.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct LockedPackage {
+    name: String,
+    version: String,
+    source: Option<String>,
+}
+
+fn locked_packages(lockfile: &Value) -> Vec<(LockedPackage, Vec<String>)> {
+    let Some(packages) = lockfile.get("package").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+    packages
+        .iter()
+        .filter_map(|package| {
+            let field = |key| package.get(key).and_then(Value::as_str).map(str::to_string);
+            let locked = LockedPackage {
+                name: field("name")?,
+                version: field("version")?,
+                source: field("source"),
+            };
+            let dependencies = package
+                .get("dependencies")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|it| it.as_str().map(str::to_string))
+                .collect();
+            Some((locked, dependencies))
+        })
+        .collect()
+}
+
+/// Find the members (packages without a source) whose resolved dependencies changed
+/// between two lockfiles, directly or through registry and git dependencies. Returns a
+/// changed dependency of each.
+pub fn lockfile_changes(old: &Value, new: &Value) -> BTreeMap<String, String> {
+    let old_packages: BTreeSet<_> = locked_packages(old).into_iter().map(|(it, _)| it).collect();
+    let new_packages = locked_packages(new);
+    // Dependencies are written as "name", "name version" or "name version (source)"
+    let resolve = |dependency: &str| {
+        let mut parts = dependency.splitn(3, ' ');
+        let name = parts.next();
+        let version = parts.next();
+        new_packages.iter().position(|(it, _)| {
+            Some(it.name.as_str()) == name && version.is_none_or(|v| v == it.version)
+        })
+    };
+
+    let mut changes = BTreeMap::new();
+    for (member, _) in new_packages.iter().filter(|(it, _)| it.source.is_none()) {
+        let mut visited = BTreeSet::new();
+        let mut queue = vec![member];
+        while let Some(package) = queue.pop() {
+            if !visited.insert(package) {
+                continue;
+            }
+            let (_, dependencies) = new_packages
+                .iter()
+                .find(|(it, _)| it == package)
+                .expect("visited packages shall be locked");
+            for dependency in dependencies {
+                let Some(index) = resolve(dependency) else {
+                    continue;
+                };
+                let dependency = &new_packages[index].0;
+                if dependency.source.is_some() && !old_packages.contains(dependency) {
+                    changes.insert(
+                        member.name.clone(),
+                        format!("{} {}", dependency.name, dependency.version),
+                    );
+                    queue.clear();
+                    break;
+                }
+                // Changes of other members are up to propagation
+                if dependency.source.is_some() {
+                    queue.push(dependency);
+                }
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGISTRY: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+    /// Build a lockfile from `(name, version, source, dependencies)` entries.
+    fn lockfile(packages: &[(&str, &str, bool, &[&str])]) -> Value {
+        let mut content = "version = 4\n".to_string();
+        for (name, version, registry, dependencies) in packages {
+            content.push_str(&format!(
+                "\n[[package]]\nname = \"{}\"\nversion = \"{}\"\n",
+                name, version
+            ));
+            if *registry {
+                content.push_str(&format!("source = \"{}\"\n", REGISTRY));
+            }
+            content.push_str(&format!("dependencies = {:?}\n", dependencies));
+        }
+        parse(&content).unwrap()
+    }
+
+    #[test]
+    fn finds_transitive_registry_changes() {
+        let old = lockfile(&[
+            ("app", "0.1.0", false, &["serde"]),
+            ("other", "0.1.0", false, &[]),
+            ("serde", "1.0.0", true, &["serde_derive"]),
+            ("serde_derive", "1.0.0", true, &[]),
+        ]);
+        let new = lockfile(&[
+            ("app", "0.1.0", false, &["serde"]),
+            ("other", "0.1.0", false, &[]),
+            ("serde", "1.0.0", true, &["serde_derive"]),
+            ("serde_derive", "1.0.1", true, &[]),
+        ]);
+        assert_eq!(
+            lockfile_changes(&old, &new),
+            BTreeMap::from([("app".to_string(), "serde_derive 1.0.1".to_string())])
+        );
+        assert!(lockfile_changes(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn resolves_ambiguous_dependencies_by_version() {
+        let old = lockfile(&[
+            ("a", "0.1.0", false, &["rand 0.8.4"]),
+            ("b", "0.1.0", false, &["rand 0.7.3"]),
+            ("rand", "0.7.3", true, &[]),
+            ("rand", "0.8.4", true, &[]),
+        ]);
+        let new = lockfile(&[
+            ("a", "0.1.0", false, &["rand 0.8.5"]),
+            ("b", "0.1.0", false, &["rand 0.7.3"]),
+            ("rand", "0.7.3", true, &[]),
+            ("rand", "0.8.5", true, &[]),
+        ]);
+        assert_eq!(
+            lockfile_changes(&old, &new),
+            BTreeMap::from([("a".to_string(), "rand 0.8.5".to_string())])
+        );
+    }
+
+    #[test]
+    fn ignores_path_dependency_changes() {
+        let old = lockfile(&[
+            ("a", "0.1.0", false, &[]),
+            ("b", "0.1.0", false, &["a", "serde"]),
+            ("serde", "1.0.0", true, &[]),
+        ]);
+        let new = lockfile(&[
+            ("a", "0.2.0", false, &[]),
+            ("b", "0.1.0", false, &["a", "serde"]),
+            ("serde", "1.0.0", true, &[]),
+        ]);
+        assert!(lockfile_changes(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn finds_inherited_workspace_changes() {
+        let old = parse(
+            r#"
+[workspace.package]
+edition = "2021"
+license = "MIT"
+
+[workspace.dependencies]
+serde = "1.0"
+libc = "0.2.100"
+rand = "0.8"
+"#,
+        )
+        .unwrap();
+        let new = parse(
+            r#"
+# Comments and key order don't matter
+[workspace.dependencies]
+rand = "0.8"
+libc = "0.2.150"
+serde = { version = "1.0", features = ["derive"] }
+
+[workspace.package]
+license = "MIT"
+edition = "2024"
+"#,
+        )
+        .unwrap();
+        let changes = WorkspaceChanges::between(&old, &new, &[]);
+        assert_eq!(
+            changes.package_fields,
+            BTreeSet::from(["edition".to_string()])
+        );
+        assert_eq!(
+            changes.dependencies,
+            BTreeSet::from(["libc".to_string(), "serde".to_string()])
+        );
+        assert!(!changes.root_package);
+
+        let member = parse(
+            r#"
+[package]
+name = "a"
+edition.workspace = true
+license.workspace = true
+
+[dependencies]
+rand.workspace = true
+
+[dev-dependencies]
+serde = { workspace = true }
+
+[target.'cfg(unix)'.dependencies]
+libc.workspace = true
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            changes.inherited_by(&member),
+            [
+                "package.edition",
+                "dev-dependencies.serde",
+                "target.cfg(unix).dependencies.libc",
+            ]
+        );
+        let unrelated = parse("[package]\nname = \"b\"\nedition = \"2021\"\n").unwrap();
+        assert!(changes.inherited_by(&unrelated).is_empty());
+    }
+
+    #[test]
+    fn finds_root_package_changes() {
+        let old = parse("[workspace]\n\n[package]\nname = \"root\"\n").unwrap();
+        let new = parse(
+            "[workspace]\nresolver = \"2\"\n\n[package]\nname = \"root\"\n\n[package.metadata.x]\ny = 1\n",
+        )
+        .unwrap();
+        let ignored = ["package.metadata".to_string()];
+        assert!(!WorkspaceChanges::between(&old, &new, &ignored).root_package);
+        assert!(WorkspaceChanges::between(&old, &new, &[]).root_package);
+    }
+}
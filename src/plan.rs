@@ -0,0 +1,78 @@
+//! Machine-readable description of what a run bumps and modifies.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use serde::{Serialize, Serializer};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Only log messages on stderr
+    Text,
+    Json,
+    Toml,
+}
+
+/// Why a package is bumped.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Reason {
+    /// Files of the package changed
+    Changed { files: Vec<PathBuf> },
+    /// The package depends on another bumped package
+    Propagated { from: String },
+    /// The package inherits the bumped workspace version
+    Inherited,
+    /// The package is bumped regardless of changes, with `--force`
+    Forced,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackagePlan {
+    pub name: String,
+    pub manifest_path: PathBuf,
+    pub old_version: String,
+    pub new_version: String,
+    pub reason: Reason,
+    /// Files modified for the version change of this package
+    pub files: Vec<PathBuf>,
+}
+
+/// A tag of the release commit, serialized as its name.
+#[derive(Clone, Debug)]
+pub struct Tag {
+    pub name: String,
+    pub message: String,
+}
+
+impl Serialize for Tag {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.name.serialize(serializer)
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Plan {
+    pub packages: Vec<PackagePlan>,
+    /// All files to be modified, including dependent manifests and `Cargo.lock`
+    pub files: Vec<PathBuf>,
+    pub tags: Vec<Tag>,
+    /// Message of the release commit
+    #[serde(skip)]
+    pub commit_message: String,
+    /// New content of each file to be modified
+    #[serde(skip)]
+    pub contents: BTreeMap<PathBuf, String>,
+}
+
+impl Plan {
+    /// Print the plan to stdout, unless the format is text.
+    pub fn print(&self, format: OutputFormat) -> anyhow::Result<()> {
+        match format {
+            OutputFormat::Text => {}
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(self)?),
+            OutputFormat::Toml => print!("{}", toml_edit::ser::to_string_pretty(self)?),
+        }
+        Ok(())
+    }
+}
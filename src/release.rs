@@ -0,0 +1,66 @@
+//! Release commit and tags.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::error::bad_input;
+use crate::plan::Tag;
+
+/// Render the release commit message. `{summary}` expands to e.g. `a 0.2.0, b 1.1.0`, and
+/// `{packages}` to one `- a 0.1.0 -> 0.2.0` line per package.
+pub fn commit_message(
+    template: &str,
+    bumped_versions: &BTreeMap<String, (String, String)>,
+) -> String {
+    let summary = bumped_versions
+        .iter()
+        .map(|(name, (_, new_version))| format!("{} {}", name, new_version))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let packages = bumped_versions
+        .iter()
+        .map(|(name, (old_version, new_version))| {
+            format!("- {} {} -> {}", name, old_version, new_version)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    template
+        .replace("{summary}", &summary)
+        .replace("{packages}", &packages)
+}
+
+/// Tags to create: one per package in independent mode, otherwise a single workspace tag,
+/// which requires all packages to share the same new version.
+pub fn release_tags(
+    config: &Config,
+    bumped_versions: &BTreeMap<String, (String, String)>,
+) -> Result<Vec<Tag>> {
+    if config.workspace.independent {
+        return Ok(bumped_versions
+            .iter()
+            .map(|(name, (_, new_version))| Tag {
+                name: config.package_tag(name, new_version),
+                message: format!("{} {}", name, new_version),
+            })
+            .collect());
+    }
+    let new_versions: BTreeSet<_> = bumped_versions
+        .values()
+        .map(|(_, new_version)| new_version)
+        .collect();
+    match new_versions.into_iter().collect::<Vec<_>>().as_slice() {
+        [] => Ok(Vec::new()),
+        [new_version] => {
+            let name = format!("{}{}", config.workspace.tag_prefix, new_version);
+            Ok(vec![Tag {
+                message: name.clone(),
+                name,
+            }])
+        }
+        _ => Err(bad_input(
+            "bumped packages have different versions, use --independent for per-package tags",
+        )),
+    }
+}
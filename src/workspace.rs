@@ -0,0 +1,898 @@
+//! Detection of affected packages, and planning and applying their version bumps.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use cargo_metadata::semver::Version;
+use cargo_metadata::{Metadata, MetadataCommand, Package, PackageId};
+use globset::Glob;
+use serde_json::Value;
+use tracing::{debug, info, warn};
+
+use crate::bump::{BumpTarget, check_upgrade, today};
+use crate::changelog;
+use crate::config::Config;
+use crate::conventional::ChangeKind;
+use crate::detect::{ChangeDetector, propagate_to_dependents};
+use crate::error::{CommandFailed, bad_input};
+use crate::files::Transaction;
+use crate::git::{
+    git_all_files, git_changed_files, git_commit, git_dirty_files, git_is_tracked, git_latest_tag,
+    git_log, git_merge_base, git_rev_exists, git_show, git_tag, git_toplevel, git_untracked_files,
+};
+use crate::manifest::{
+    inherits_workspace_version, read_toml_document, update_lockfile, update_path_dependencies,
+};
+use crate::manifest_diff::{self, WorkspaceChanges};
+use crate::plan::{PackagePlan, Plan, Reason};
+use crate::release::{commit_message, release_tags};
+
+/// A Cargo workspace inside a git repository.
+pub struct Workspace {
+    metadata: Metadata,
+    toplevel: PathBuf,
+    /// Settings from the manifests, which may be overridden before detecting changes
+    pub config: Config,
+}
+
+/// A workspace member to bump.
+#[derive(Debug)]
+pub struct AffectedPackage<'a> {
+    pub package: &'a Package,
+    /// Revision the package is compared against, `None` meaning all its files are changed
+    pub base: Option<String>,
+    /// Revision the changes are detected at
+    pub to: String,
+    pub reason: Reason,
+}
+
+/// How to find affected packages.
+#[derive(Clone, Debug, Default)]
+pub struct DetectOptions {
+    /// Revision to compare against instead of the latest release tags, or a range like
+    /// `A..B`, or `A...B` to compare against the merge base of `A` and `B`
+    pub from: Option<String>,
+    /// Revision to detect changes at, HEAD by default
+    pub to: Option<String>,
+    /// Also consider staged and unstaged changes of tracked files
+    pub include_worktree: bool,
+    /// Also consider untracked files, implies `include_worktree`
+    pub include_untracked: bool,
+    /// Only keep affected packages matching these names or glob patterns, if any
+    pub packages: Vec<String>,
+    /// Leave out affected packages matching these names or glob patterns
+    pub exclude: Vec<String>,
+    /// Consider packages matching these names or glob patterns affected regardless of changes
+    pub force: Vec<String>,
+}
+
+/// How to bump affected packages.
+#[derive(Clone, Debug)]
+pub struct BumpOptions {
+    pub target: BumpTarget,
+    /// Tag the release commit
+    pub tag: bool,
+    /// Allow new versions not greater than the current ones
+    pub allow_downgrade: bool,
+}
+
+/// Members whose names match any of `specs`, names or glob patterns, failing if a spec
+/// matches no member.
+fn matching_members<'a>(
+    members: &[&'a Package],
+    specs: &[String],
+) -> Result<BTreeSet<&'a PackageId>> {
+    let mut ids = BTreeSet::new();
+    for spec in specs {
+        let matcher = Glob::new(spec)
+            .map_err(|err| bad_input(format!("invalid package pattern '{}': {}", spec, err)))?
+            .compile_matcher();
+        let matching: Vec<_> = members
+            .iter()
+            .filter(|p| matcher.is_match(p.name.as_str()))
+            .map(|p| &p.id)
+            .collect();
+        if matching.is_empty() {
+            return Err(bad_input(format!(
+                "package '{}' is not a workspace member",
+                spec
+            )));
+        }
+        ids.extend(matching);
+    }
+    Ok(ids)
+}
+
+impl Workspace {
+    /// Load the workspace of `manifest_path`, or of the current directory if `None`.
+    pub fn load(manifest_path: Option<&Path>) -> Result<Self> {
+        let mut command = MetadataCommand::new();
+        if let Some(manifest_path) = manifest_path {
+            command.manifest_path(manifest_path);
+        }
+        let metadata = command.no_deps().exec().map_err(|err| CommandFailed {
+            command: "cargo metadata".to_string(),
+            stderr: err.to_string(),
+        })?;
+
+        let members: Vec<_> = metadata
+            .packages
+            .iter()
+            .filter(|p| metadata.workspace_members.contains(&p.id))
+            .collect();
+        let config =
+            Config::load(&metadata, &members).map_err(|err| bad_input(format!("{:#}", err)))?;
+
+        let workspace_root = metadata.workspace_root.as_std_path();
+        let toplevel = git_toplevel(workspace_root).context("cannot get git toplevel directory")?;
+        if !workspace_root.starts_with(&toplevel) {
+            return Err(bad_input(format!(
+                "workspace root {} is not inside git toplevel {}",
+                workspace_root.display(),
+                toplevel.display()
+            )));
+        }
+
+        Ok(Self {
+            metadata,
+            toplevel,
+            config,
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        self.metadata.workspace_root.as_std_path()
+    }
+
+    pub fn members(&self) -> Vec<&Package> {
+        self.metadata
+            .packages
+            .iter()
+            .filter(|p| self.metadata.workspace_members.contains(&p.id))
+            .collect()
+    }
+
+    /// Tracked files with uncommitted changes.
+    pub fn dirty_files(&self) -> Result<Vec<PathBuf>> {
+        git_dirty_files(&self.toplevel).context("cannot get git status")
+    }
+
+    /// Resolve the revisions to compare, the first one being `None` if it has to be found
+    /// with tags.
+    fn range(&self, options: &DetectOptions) -> Result<(Option<String>, String)> {
+        let (from, to) = match options.from.as_deref() {
+            Some(range) if range.contains("..") => {
+                if options.to.is_some() {
+                    return Err(bad_input(format!(
+                        "--to cannot be combined with the range '{}'",
+                        range
+                    )));
+                }
+                let (from, to, merge_base) = match range.split_once("...") {
+                    Some((from, to)) => (from, to, true),
+                    None => {
+                        let (from, to) = range.split_once("..").expect("range shall contain '..'");
+                        (from, to, false)
+                    }
+                };
+                let from = if from.is_empty() { "HEAD" } else { from };
+                let to = if to.is_empty() { "HEAD" } else { to };
+                let from = if merge_base {
+                    let merge_base =
+                        git_merge_base(&self.toplevel, from, to).with_context(|| {
+                            format!("cannot find the merge base of '{}' and '{}'", from, to)
+                        })?;
+                    info!(
+                        "Using merge base {} of '{}' and '{}' for comparison",
+                        merge_base, from, to
+                    );
+                    merge_base
+                } else {
+                    from.to_string()
+                };
+                (Some(from), to.to_string())
+            }
+            from => (
+                from.map(str::to_string),
+                options.to.clone().unwrap_or_else(|| "HEAD".to_string()),
+            ),
+        };
+        if !git_rev_exists(&self.toplevel, &to)? {
+            return Err(bad_input(format!("unknown revision '{}'", to)));
+        }
+        Ok((from, to))
+    }
+
+    /// Comparison base of each member, `None` meaning all its files are considered changed.
+    fn bases<'a>(
+        &self,
+        members: &[&'a Package],
+        from: Option<&str>,
+        to: &str,
+    ) -> Result<BTreeMap<&'a PackageId, Option<String>>> {
+        let config = &self.config;
+        let toplevel = &self.toplevel;
+        let mut bases = BTreeMap::new();
+        if let Some(old_tag) = from {
+            let mut old_tag = old_tag.to_string();
+            let prefixed_tag = format!("{}{}", config.workspace.tag_prefix, old_tag);
+            if old_tag.parse::<Version>().is_ok()
+                && !git_rev_exists(toplevel, &old_tag)?
+                && git_rev_exists(toplevel, &prefixed_tag)?
+            {
+                info!("Using tag '{}' for '{}'", prefixed_tag, old_tag);
+                old_tag = prefixed_tag;
+            }
+            if !git_rev_exists(toplevel, &old_tag)? {
+                return Err(bad_input(format!("unknown revision '{}'", old_tag)));
+            }
+            for package in members {
+                bases.insert(&package.id, Some(old_tag.clone()));
+            }
+        } else if config.workspace.independent {
+            for package in members {
+                let tag = config.package_tag(&package.name, &package.version.to_string());
+                let base = if git_rev_exists(toplevel, &tag)? {
+                    info!("Using tag '{}' for package '{}'", tag, package.name);
+                    Some(tag)
+                } else {
+                    let pattern = config.package_tag(&package.name, "*");
+                    let latest_tag = git_latest_tag(toplevel, &pattern, to)?;
+                    match &latest_tag {
+                        Some(latest_tag) => warn!(
+                            "Tag '{}' not found, using tag '{}' for package '{}'",
+                            tag, latest_tag, package.name
+                        ),
+                        None => warn!(
+                            "No tag matching '{}' found, considering all files of package '{}' as changed",
+                            pattern, package.name
+                        ),
+                    }
+                    latest_tag
+                };
+                bases.insert(&package.id, base);
+            }
+        } else {
+            let tag_pattern = config.tag_pattern();
+            let mut latest_tags = BTreeMap::new();
+            for package in members {
+                let pattern = tag_pattern.replace("{name}", &package.name);
+                if !latest_tags.contains_key(&pattern) {
+                    let latest_tag = git_latest_tag(toplevel, &pattern, to)?;
+                    match &latest_tag {
+                        Some(tag) => {
+                            info!("Using tag '{}' matching '{}' for comparison", tag, pattern)
+                        }
+                        None => warn!(
+                            "No tag matching '{}' found, considering all files as changed",
+                            pattern
+                        ),
+                    }
+                    latest_tags.insert(pattern.clone(), latest_tag);
+                }
+                bases.insert(&package.id, latest_tags[&pattern].clone());
+            }
+        }
+        Ok(bases)
+    }
+
+    /// Read `file` at `rev`, or in the working tree if `None`.
+    fn read_at(&self, rev: Option<&str>, file: &Path) -> Result<Option<String>> {
+        match rev {
+            Some(rev) => git_show(&self.toplevel, rev, file)
+                .with_context(|| format!("cannot read {} at '{}'", file.display(), rev)),
+            None if file.exists() => {
+                Ok(Some(std::fs::read_to_string(file).with_context(|| {
+                    format!("cannot read {}", file.display())
+                })?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Compare the root manifest and `Cargo.lock` semantically between `base` and `to`,
+    /// removing them from `changed_files` so that they are not attributed to a root package
+    /// by path, along with member manifests without significant changes. Returns the members
+    /// inheriting changed workspace fields or dependencies, and with `lockfile-changes`, those
+    /// whose resolved dependencies changed.
+    fn manifest_changes<'a>(
+        &self,
+        members: &[&'a Package],
+        base: &str,
+        to: Option<&str>,
+        changed_files: &mut Vec<PathBuf>,
+    ) -> Result<BTreeMap<&'a PackageId, Vec<PathBuf>>> {
+        let mut changes: BTreeMap<_, Vec<PathBuf>> = BTreeMap::new();
+        let root_manifest_path = self.root().join("Cargo.toml");
+        let lockfile_path = self.root().join("Cargo.lock");
+        let ignored = &self.config.workspace.manifest_ignore;
+        let read = |rev, file: &Path| -> Result<Option<Value>> {
+            match self.read_at(rev, file)? {
+                Some(content) => {
+                    Ok(Some(manifest_diff::parse(&content).with_context(|| {
+                        format!("cannot parse {}", file.display())
+                    })?))
+                }
+                None => Ok(None),
+            }
+        };
+
+        if changed_files.contains(&root_manifest_path)
+            && let (Some(old), Some(new)) = (
+                read(Some(base), &root_manifest_path)?,
+                read(to, &root_manifest_path)?,
+            )
+        {
+            let workspace_changes = WorkspaceChanges::between(&old, &new, ignored);
+            if !workspace_changes.root_package {
+                changed_files.retain(|it| *it != root_manifest_path);
+            }
+            for &package in members {
+                let manifest_path = package.manifest_path.as_std_path();
+                let Some(manifest) = read(to, manifest_path)? else {
+                    continue;
+                };
+                let inherited = workspace_changes.inherited_by(&manifest);
+                if !inherited.is_empty() {
+                    info!(
+                        "Package '{}' inherits changed workspace settings: {}",
+                        package.name,
+                        inherited.join(", ")
+                    );
+                    changes
+                        .entry(&package.id)
+                        .or_default()
+                        .push(root_manifest_path.clone());
+                }
+            }
+        }
+
+        // Reformatting or editing e.g. `[package.metadata]` doesn't change the published package
+        for package in members {
+            let manifest_path = package.manifest_path.as_std_path();
+            if manifest_path == root_manifest_path
+                || !changed_files.iter().any(|it| it == manifest_path)
+            {
+                continue;
+            }
+            if let (Some(old), Some(new)) =
+                (read(Some(base), manifest_path)?, read(to, manifest_path)?)
+                && manifest_diff::without(&old, ignored) == manifest_diff::without(&new, ignored)
+            {
+                debug!(
+                    "Manifest of package '{}' has no significant changes",
+                    package.name
+                );
+                changed_files.retain(|it| it != manifest_path);
+            }
+        }
+
+        if changed_files.contains(&lockfile_path) {
+            changed_files.retain(|it| *it != lockfile_path);
+            if self.config.workspace.lockfile_changes
+                && let (Some(old), Some(new)) =
+                    (read(Some(base), &lockfile_path)?, read(to, &lockfile_path)?)
+            {
+                for (name, dependency) in manifest_diff::lockfile_changes(&old, &new) {
+                    if let Some(package) = members.iter().find(|p| *p.name == name) {
+                        info!(
+                            "Package '{}' has changed dependencies in Cargo.lock, e.g. {}",
+                            package.name, dependency
+                        );
+                        changes
+                            .entry(&package.id)
+                            .or_default()
+                            .push(lockfile_path.clone());
+                    }
+                }
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Find the members changed since the `from` revision, or since their latest release
+    /// tags, along with their dependents if propagation is configured. Skipped packages are
+    /// left out.
+    pub fn detect_affected(&self, options: &DetectOptions) -> Result<Vec<AffectedPackage<'_>>> {
+        let members = self.members();
+        let selected = matching_members(&members, &options.packages)?;
+        let excluded = matching_members(&members, &options.exclude)?;
+        let forced = matching_members(&members, &options.force)?;
+        let (from, to) = self.range(options)?;
+        let include_worktree = options.include_worktree || options.include_untracked;
+        if include_worktree && to != "HEAD" {
+            return Err(bad_input(format!(
+                "changes of the working tree cannot be detected at '{}'",
+                to
+            )));
+        }
+        let bases = self.bases(&members, from.as_deref(), &to)?;
+        // Revision to detect changes at, `None` for the working tree and index
+        let changes_at = (!include_worktree).then_some(to.as_str());
+
+        let mut changed_files_by_base = BTreeMap::new();
+        for base in bases.values() {
+            if changed_files_by_base.contains_key(base) {
+                continue;
+            }
+            let mut changed_files = match base {
+                Some(base) => git_changed_files(&self.toplevel, base, changes_at)
+                    .with_context(|| format!("cannot get files changed since '{}'", base))?,
+                None => git_all_files(&self.toplevel, changes_at)
+                    .context("cannot list files tracked by git")?,
+            };
+            if options.include_untracked {
+                changed_files.extend(
+                    git_untracked_files(&self.toplevel).context("cannot list untracked files")?,
+                );
+            }
+            changed_files_by_base.insert(base.clone(), changed_files);
+        }
+
+        let detector = ChangeDetector::new(&self.metadata, &members, &self.config)?;
+        let mut package_changes = BTreeMap::new();
+        for (base, changed_files) in &changed_files_by_base {
+            let mut changed_files = changed_files.clone();
+            let mut changes = match base {
+                Some(base) => {
+                    self.manifest_changes(&members, base, changes_at, &mut changed_files)?
+                }
+                None => BTreeMap::new(),
+            };
+            for (id, files) in detector.changed_packages(&changed_files) {
+                let package_files = changes.entry(id).or_default();
+                package_files.extend(files);
+                package_files.sort();
+                package_files.dedup();
+            }
+            // Files changed since a base only count for the members compared against it
+            package_changes.extend(changes.into_iter().filter(|(id, _)| bases[id] == *base));
+        }
+
+        let mut affected_packages = Vec::new();
+        for &package in &members {
+            if package_changes.contains_key(&package.id) {
+                debug!("Package '{}' is affected", package.name);
+                affected_packages.push(package);
+            } else if forced.contains(&package.id) {
+                info!("Package '{}' is affected as it is forced", package.name);
+                affected_packages.push(package);
+            } else {
+                debug!("Package '{}' is not affected", package.name);
+            }
+        }
+
+        let mut propagated_from = if self.config.workspace.propagate {
+            propagate_to_dependents(&members, &mut affected_packages)
+        } else {
+            BTreeMap::new()
+        };
+
+        // Uncommitted changes are easily forgotten when releasing locally, but have nothing to
+        // do with changes at another revision
+        let mut uncommitted_files = Vec::new();
+        if to == "HEAD" && !include_worktree {
+            uncommitted_files.extend(self.dirty_files()?);
+        }
+        if to == "HEAD" && !options.include_untracked {
+            uncommitted_files.extend(
+                git_untracked_files(&self.toplevel).context("cannot list untracked files")?,
+            );
+        }
+        for (id, files) in detector.changed_packages(&uncommitted_files) {
+            if let Some(package) = members
+                .iter()
+                .find(|p| p.id == *id && !affected_packages.contains(p))
+            {
+                warn!(
+                    "Package '{}' has uncommitted changes which are not considered (e.g. {}), see --include-worktree and --include-untracked",
+                    package.name,
+                    files[0].display()
+                );
+            }
+        }
+
+        Ok(affected_packages
+            .into_iter()
+            .filter(|p| {
+                // Forcing a package is explicit enough to override the selection and config
+                if forced.contains(&p.id) {
+                    return true;
+                }
+                if (!selected.is_empty() && !selected.contains(&p.id)) || excluded.contains(&p.id) {
+                    info!("Package '{}' is not selected", p.name);
+                    return false;
+                }
+                let skip = self.config.package(p).skip;
+                if skip {
+                    info!("Package '{}' is skipped by configuration", p.name);
+                }
+                !skip
+            })
+            .map(|package| {
+                let reason = match package_changes.remove(&package.id) {
+                    Some(files) => Reason::Changed { files },
+                    None => match propagated_from.remove(&package.id) {
+                        Some(from) => Reason::Propagated {
+                            from: from.to_string(),
+                        },
+                        None => Reason::Forced,
+                    },
+                };
+                AffectedPackage {
+                    package,
+                    base: bases[&package.id].clone(),
+                    to: to.clone(),
+                    reason,
+                }
+            })
+            .collect())
+    }
+
+    /// Compute the new versions of `affected` packages and the resulting file contents,
+    /// without modifying anything.
+    pub fn plan_bump(&self, affected: &[AffectedPackage], options: &BumpOptions) -> Result<Plan> {
+        let config = &self.config;
+        let members = self.members();
+        let bump_target = &options.target;
+
+        // Messages of the commits touching each package since its base, only needed for
+        // `auto` and changelogs
+        let mut package_commits: BTreeMap<_, Vec<String>> = BTreeMap::new();
+        if matches!(bump_target, BumpTarget::Auto) || config.workspace.changelog {
+            let detector = ChangeDetector::new(&self.metadata, &members, config)?;
+            let ranges: BTreeSet<_> = affected.iter().map(|it| (&it.base, &it.to)).collect();
+            for (base, to) in ranges {
+                let commits =
+                    git_log(&self.toplevel, base.as_deref(), to).context("cannot get commits")?;
+                for commit in commits {
+                    for id in detector.changed_packages(&commit.files).into_keys() {
+                        if affected
+                            .iter()
+                            .any(|it| it.package.id == *id && it.base == *base && it.to == *to)
+                        {
+                            package_commits
+                                .entry(id)
+                                .or_default()
+                                .push(commit.message.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        // Most significant change of each package since its base, only needed for `auto`
+        let mut change_kinds = BTreeMap::new();
+        if matches!(bump_target, BumpTarget::Auto) {
+            for affected_package in affected {
+                let package = affected_package.package;
+                let change_kind = package_commits
+                    .get(&package.id)
+                    .into_iter()
+                    .flatten()
+                    .map(|message| ChangeKind::of(message))
+                    .max()
+                    .unwrap_or_default();
+                info!(
+                    "Most significant change of package '{}': {:?}",
+                    package.name, change_kind
+                );
+                change_kinds.insert(&package.id, change_kind);
+            }
+        }
+
+        // Load every member manifest and the root manifest up front, so that inheritance and
+        // path dependencies can be checked for all of them and a manifest edited twice (e.g. a
+        // root package) is only written once.
+        let root_manifest_path = self.root().join("Cargo.toml");
+        let mut manifests = BTreeMap::new();
+        for manifest_path in members
+            .iter()
+            .map(|p| p.manifest_path.as_std_path())
+            .chain([root_manifest_path.as_path()])
+        {
+            if !manifests.contains_key(manifest_path) {
+                manifests.insert(
+                    manifest_path.to_path_buf(),
+                    read_toml_document(manifest_path)?,
+                );
+            }
+        }
+        let mut modified_manifests = BTreeSet::new();
+        let mut inheriting_packages = Vec::new();
+        let mut inheriting_change_kind = ChangeKind::default();
+        let mut new_versions = BTreeMap::new();
+
+        for affected_package in affected {
+            let package = affected_package.package;
+            let manifest_path = package.manifest_path.as_std_path();
+            let manifest_content = manifests
+                .get_mut(manifest_path)
+                .expect("all member manifests shall be loaded");
+            if inherits_workspace_version(manifest_content) {
+                debug!("Package '{}' inherits the workspace version", package.name);
+                inheriting_packages.push(package.name.as_str());
+                inheriting_change_kind = inheriting_change_kind
+                    .max(change_kinds.get(&package.id).copied().unwrap_or_default());
+                continue;
+            }
+            let change_kind = change_kinds.get(&package.id).copied().unwrap_or_default();
+            let new_version = bump_target
+                .resolve(change_kind, &package.version)
+                .next_version(&package.version)
+                .with_context(|| format!("cannot bump version of package '{}'", package.name))?;
+            check_upgrade(
+                &format!("package '{}'", package.name),
+                &package.version,
+                &new_version,
+                options.allow_downgrade,
+            )?;
+            let new_version = new_version.to_string();
+            info!(
+                "Setting version of package '{}' from '{}' to '{}'",
+                package.name, package.version, new_version
+            );
+            let version_item = manifest_content
+                .get_mut("package")
+                .and_then(|it| it.as_table_mut())
+                .and_then(|it| it.get_mut("version"))
+                .ok_or_else(|| {
+                    bad_input(format!(
+                        "missing package.version in {}",
+                        manifest_path.display()
+                    ))
+                })?;
+            *version_item = toml_edit::value(new_version.clone());
+            modified_manifests.insert(manifest_path.to_path_buf());
+            new_versions.insert(package.name.to_string(), new_version);
+        }
+
+        if !inheriting_packages.is_empty() {
+            let all_inheriting_packages: Vec<_> = members
+                .iter()
+                .filter(|p| inherits_workspace_version(&manifests[p.manifest_path.as_std_path()]))
+                .map(|p| p.name.as_str())
+                .collect();
+            let version_item = manifests
+                .get_mut(&root_manifest_path)
+                .expect("root manifest shall be loaded")
+                .get_mut("workspace")
+                .and_then(|it| it.get_mut("package"))
+                .and_then(|it| it.get_mut("version"))
+                .ok_or_else(|| {
+                    bad_input(format!(
+                        "missing workspace.package.version in {}",
+                        root_manifest_path.display()
+                    ))
+                })?;
+            let current_version: Version = version_item
+                .as_str()
+                .and_then(|it| it.parse().ok())
+                .ok_or_else(|| {
+                    bad_input(format!(
+                        "workspace.package.version in {} is not a valid version",
+                        root_manifest_path.display()
+                    ))
+                })?;
+            let new_version = bump_target
+                .resolve(inheriting_change_kind, &current_version)
+                .next_version(&current_version)
+                .context("cannot bump workspace version")?;
+            check_upgrade(
+                "the workspace",
+                &current_version,
+                &new_version,
+                options.allow_downgrade,
+            )?;
+            let new_version = new_version.to_string();
+            info!(
+                "Setting workspace version from '{}' to '{}' (affected: {})",
+                current_version,
+                new_version,
+                inheriting_packages.join(", ")
+            );
+            info!(
+                "Packages inheriting the workspace version: {}",
+                all_inheriting_packages.join(", ")
+            );
+            for name in &all_inheriting_packages {
+                if !inheriting_packages.contains(name) {
+                    warn!(
+                        "Package '{}' is not affected but will be bumped as it inherits the workspace version",
+                        name
+                    );
+                }
+            }
+            *version_item = toml_edit::value(new_version.clone());
+            modified_manifests.insert(root_manifest_path.clone());
+            for name in all_inheriting_packages {
+                new_versions.insert(name.to_string(), new_version.clone());
+            }
+        }
+
+        for (manifest_path, manifest_content) in &mut manifests {
+            if update_path_dependencies(manifest_path, manifest_content, &new_versions) {
+                modified_manifests.insert(manifest_path.clone());
+            }
+        }
+
+        let bumped_versions: BTreeMap<_, _> = members
+            .iter()
+            .filter_map(|p| {
+                let new_version = new_versions.get(p.name.as_str())?;
+                Some((
+                    p.name.to_string(),
+                    (p.version.to_string(), new_version.clone()),
+                ))
+            })
+            .collect();
+        let lockfile_path = self.root().join("Cargo.lock");
+        let mut lockfile = None;
+        if lockfile_path.exists() {
+            let mut lockfile_content = read_toml_document(&lockfile_path)?;
+            if update_lockfile(&mut lockfile_content, &bumped_versions) {
+                lockfile = Some(lockfile_content);
+            }
+        } else {
+            debug!("No Cargo.lock found, skipping lockfile update");
+        }
+
+        let mut changelogs = BTreeMap::new();
+        if config.workspace.changelog {
+            let date = today();
+            let date = format!("{}-{:02}-{:02}", date / 10000, date / 100 % 100, date % 100);
+            for &package in &members {
+                let Some((_, new_version)) = bumped_versions.get(package.name.as_str()) else {
+                    continue;
+                };
+                let changelog_path = package
+                    .manifest_path
+                    .as_std_path()
+                    .parent()
+                    .expect("manifest path shall have a parent directory")
+                    .join(&config.workspace.changelog_file);
+                let messages: Vec<_> = package_commits
+                    .get(&package.id)
+                    .into_iter()
+                    .flatten()
+                    .map(String::as_str)
+                    .collect();
+                let section = changelog::render_section(new_version, &date, &messages);
+                let existing = if changelog_path.exists() {
+                    Some(
+                        std::fs::read_to_string(&changelog_path)
+                            .with_context(|| format!("cannot read {}", changelog_path.display()))?,
+                    )
+                } else {
+                    None
+                };
+                info!(
+                    "Adding section for '{}' to {}",
+                    new_version,
+                    changelog_path.display()
+                );
+                changelogs.insert(
+                    changelog_path,
+                    changelog::prepend_section(existing.as_deref(), &section),
+                );
+            }
+        }
+
+        let tags = if options.tag {
+            release_tags(config, &bumped_versions)?
+        } else {
+            Vec::new()
+        };
+        for tag in &tags {
+            if git_rev_exists(&self.toplevel, &format!("refs/tags/{}", tag.name))? {
+                return Err(bad_input(format!("tag '{}' already exists", tag.name)));
+            }
+        }
+
+        let mut plan = Plan {
+            tags,
+            commit_message: commit_message(&config.workspace.commit_message, &bumped_versions),
+            ..Plan::default()
+        };
+        for &package in &members {
+            let Some((old_version, new_version)) = bumped_versions.get(package.name.as_str())
+            else {
+                continue;
+            };
+            let manifest_path = package.manifest_path.as_std_path();
+            let manifest_dir = manifest_path
+                .parent()
+                .expect("manifest path shall have a parent directory");
+            let reason = match affected.iter().find(|it| it.package.id == package.id) {
+                Some(affected_package) => affected_package.reason.clone(),
+                None => Reason::Inherited,
+            };
+            let mut files = Vec::new();
+            if inherits_workspace_version(&manifests[manifest_path]) {
+                files.push(root_manifest_path.clone());
+            } else {
+                files.push(manifest_path.to_path_buf());
+            }
+            files.extend(
+                changelogs
+                    .keys()
+                    .filter(|it| it.parent() == Some(manifest_dir))
+                    .cloned(),
+            );
+            plan.packages.push(PackagePlan {
+                name: package.name.to_string(),
+                manifest_path: manifest_path.to_path_buf(),
+                old_version: old_version.clone(),
+                new_version: new_version.clone(),
+                reason,
+                files,
+            });
+        }
+        for manifest_path in modified_manifests {
+            let content = manifests[&manifest_path].to_string();
+            plan.files.push(manifest_path.clone());
+            plan.contents.insert(manifest_path, content);
+        }
+        if let Some(lockfile) = lockfile {
+            plan.files.push(lockfile_path.clone());
+            plan.contents.insert(lockfile_path, lockfile.to_string());
+        }
+        plan.files.extend(changelogs.keys().cloned());
+        plan.contents.extend(changelogs);
+        Ok(plan)
+    }
+
+    /// Write the files modified by `plan`, each atomically. If any write fails or the
+    /// workspace cannot be loaded afterwards, all files are restored.
+    pub fn apply_plan(&self, plan: &Plan) -> Result<()> {
+        let mut transaction = Transaction::default();
+        let result = self.write_plan(plan, &mut transaction);
+        if result.is_err() {
+            warn!("Restoring the files modified so far");
+            transaction.rollback();
+        }
+        result
+    }
+
+    fn write_plan(&self, plan: &Plan, transaction: &mut Transaction) -> Result<()> {
+        for path in &plan.files {
+            info!("Updating {}", path.display());
+            transaction.write(path, &plan.contents[path])?;
+        }
+        MetadataCommand::new()
+            .manifest_path(self.metadata.workspace_root.join("Cargo.toml"))
+            .no_deps()
+            .other_options(vec!["--offline".to_string()])
+            .exec()
+            .map_err(|err| CommandFailed {
+                command: "cargo metadata".to_string(),
+                stderr: err.to_string(),
+            })
+            .context("the workspace is broken by the updated files")?;
+        Ok(())
+    }
+
+    /// Commit the files modified by an applied `plan`, and create its tags.
+    pub fn commit_plan(&self, plan: &Plan) -> Result<()> {
+        let lockfile_path = self.root().join("Cargo.lock");
+        let mut files = Vec::new();
+        for path in &plan.files {
+            // An ignored lockfile stays out of the commit
+            if *path != lockfile_path || git_is_tracked(&self.toplevel, path)? {
+                files.push(path.clone());
+            }
+        }
+        if files.is_empty() {
+            return Ok(());
+        }
+        git_commit(&self.toplevel, &files, &plan.commit_message)
+            .context("cannot create release commit")?;
+        for tag in &plan.tags {
+            info!("Creating tag '{}'", tag.name);
+            git_tag(&self.toplevel, &tag.name, &tag.message)
+                .with_context(|| format!("cannot create tag '{}'", tag.name))?;
+        }
+        Ok(())
+    }
+}